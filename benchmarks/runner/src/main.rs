@@ -1,4 +1,15 @@
 use clap::*;
+use futures_util::future::FutureExt;
+use futures_util::stream::StreamExt;
+use serde::Serialize;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::process::Command as TokioCommand;
+
+/// Languages the harness knows how to drive, in the order their flags are declared below.
+const LANGUAGES: &[&str] = &["csharp", "java", "python", "rust", "nodejs", "go"];
+
+const VALKEY_IMAGE: &str = "valkey/valkey:latest";
 
 #[tokio::main]
 async fn main() {
@@ -37,6 +48,40 @@ async fn main() {
                         .required(false)
                         .num_args(0),
                 )
+                .arg(
+                    arg!(--clients <COUNT> "Number of concurrent clients")
+                        .required(false)
+                        .value_parser(value_parser!(u32))
+                        .default_value("1"),
+                )
+                .arg(
+                    arg!(--tasks <COUNT> "Number of concurrent tasks per client")
+                        .required(false)
+                        .value_parser(value_parser!(u32))
+                        .default_value("1"),
+                )
+                .arg(
+                    arg!(--"data-size" <BYTES> "Size, in bytes, of the values used in SET-style commands")
+                        .required(false)
+                        .value_parser(value_parser!(u32))
+                        .default_value("100"),
+                )
+                .arg(
+                    arg!(--"command-mix" <MIX> "Ratio of GET to SET commands, e.g. \"80:20\"")
+                        .required(false)
+                        .default_value("80:20"),
+                )
+                .arg(
+                    arg!(--requests <COUNT> "Total number of requests to issue per language")
+                        .required(false)
+                        .value_parser(value_parser!(u64))
+                        .default_value("100000"),
+                )
+                .arg(
+                    arg!(--format <FORMAT> "Output format: \"table\" (default) or \"json\"")
+                        .required(false)
+                        .default_value("table"),
+                )
                 .subcommand(
                     Command::new("docker").about("Run the benchmark using docker containers"),
                 )
@@ -53,51 +98,353 @@ async fn main() {
     }
 }
 
-async fn command_run(matches: &ArgMatches) {
-    if let Some(docker_matches) = matches.subcommand_matches("docker") {
-        command_run_docker(matches, docker_matches).await;
+/// Workload shape shared by every language run: how many clients/tasks drive load, how large the
+/// values are, what fraction of commands are GET vs SET, and how many requests to issue in total.
+#[derive(Clone)]
+struct WorkloadConfig {
+    clients: u32,
+    concurrent_tasks: u32,
+    data_size: u32,
+    command_mix: String,
+    total_requests: u64,
+}
+
+impl WorkloadConfig {
+    fn from_matches(run_matches: &ArgMatches) -> Self {
+        Self {
+            clients: *run_matches.get_one::<u32>("clients").unwrap(),
+            concurrent_tasks: *run_matches.get_one::<u32>("tasks").unwrap(),
+            data_size: *run_matches.get_one::<u32>("data-size").unwrap(),
+            command_mix: run_matches.get_one::<String>("command-mix").unwrap().clone(),
+            total_requests: *run_matches.get_one::<u64>("requests").unwrap(),
+        }
+    }
+}
+
+/// Latency percentiles and throughput collected for a single language's run.
+#[derive(Serialize)]
+struct LanguageResult {
+    language: String,
+    host: String,
+    port: u16,
+    total_requests: u64,
+    duration_secs: f64,
+    throughput_rps: f64,
+    p50_ms: f64,
+    p90_ms: f64,
+    p99_ms: f64,
+}
+
+fn selected_languages(run_matches: &ArgMatches) -> Vec<&'static str> {
+    let flags: Vec<bool> = LANGUAGES
+        .iter()
+        .map(|lang| run_matches.get_flag(lang))
+        .collect();
+    let all = !flags.iter().any(|&flag| flag);
+    LANGUAGES
+        .iter()
+        .zip(flags)
+        .filter(|(_, flag)| all || *flag)
+        .map(|(lang, _)| *lang)
+        .collect()
+}
+
+async fn command_run(run_matches: &ArgMatches) {
+    if let Some(docker_matches) = run_matches.subcommand_matches("docker") {
+        if let Err(e) = command_run_docker(run_matches, docker_matches).await {
+            eprintln!("Docker benchmark run failed: {e}");
+            std::process::exit(1);
+        }
+        return;
     }
-    if let Some(standalone_matches) = matches.subcommand_matches("standalone") {
-        command_run_standalone(matches, standalone_matches).await;
+    if let Some(standalone_matches) = run_matches.subcommand_matches("standalone") {
+        command_run_standalone(run_matches, standalone_matches).await;
+        return;
     }
     panic!("No subcommand specified");
 }
 
-async fn command_run_docker(run_matches: &ArgMatches, docker_matches: &ArgMatches) -> Result<(), bollard::errors::Error> {
-    let csharp = run_matches.get_flag("csharp");
-    let java = run_matches.get_flag("java");
-    let python = run_matches.get_flag("python");
-    let rust = run_matches.get_flag("rust");
-    let nodejs = run_matches.get_flag("nodejs");
-    let go = run_matches.get_flag("go");
-    let all = !csharp && !java && !python && !rust && !nodejs && !go;
+/// Pulls, starts, and tears down (on success, error, or panic) a Valkey container for the
+/// duration of a benchmark run.
+struct ValkeyContainer {
+    docker: bollard::Docker,
+    container_id: String,
+    port: u16,
+}
+
+impl ValkeyContainer {
+    async fn start(docker: bollard::Docker) -> Result<Self, bollard::errors::Error> {
+        let mut pull_stream = docker.create_image(
+            Some(bollard::image::CreateImageOptions {
+                from_image: VALKEY_IMAGE,
+                ..Default::default()
+            }),
+            None,
+            None,
+        );
+        while let Some(progress) = pull_stream.next().await {
+            progress?;
+        }
+
+        let port = free_local_port().await?;
+        let name = format!("glide-bench-valkey-{}", std::process::id());
+        let port_binding = "6379/tcp".to_string();
+        let host_config = bollard::service::HostConfig {
+            port_bindings: Some(std::collections::HashMap::from([(
+                port_binding.clone(),
+                Some(vec![bollard::service::PortBinding {
+                    host_ip: Some("127.0.0.1".to_string()),
+                    host_port: Some(port.to_string()),
+                }]),
+            )])),
+            ..Default::default()
+        };
+        let container = docker
+            .create_container(
+                Some(bollard::container::CreateContainerOptions {
+                    name: name.as_str(),
+                    platform: None,
+                }),
+                bollard::container::Config {
+                    image: Some(VALKEY_IMAGE),
+                    exposed_ports: Some(std::collections::HashMap::from([(
+                        port_binding,
+                        std::collections::HashMap::new(),
+                    )])),
+                    host_config: Some(host_config),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let container = Self {
+            docker,
+            container_id: container.id,
+            port,
+        };
+        let ready = match container
+            .docker
+            .start_container(
+                &container.container_id,
+                None::<bollard::container::StartContainerOptions<String>>,
+            )
+            .await
+        {
+            Ok(()) => container.wait_until_ready().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ready {
+            container.teardown().await;
+            return Err(e);
+        }
+        Ok(container)
+    }
+
+    /// Polls the mapped port until a TCP connection succeeds or a 30s deadline is hit.
+    async fn wait_until_ready(&self) -> Result<(), bollard::errors::Error> {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            if tokio::net::TcpStream::connect(("127.0.0.1", self.port))
+                .await
+                .is_ok()
+            {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(bollard::errors::Error::IOError {
+                    err: std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Valkey container did not become ready in time",
+                    ),
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
 
+    async fn teardown(&self) {
+        let _ = self
+            .docker
+            .remove_container(
+                &self.container_id,
+                Some(bollard::container::RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await;
+    }
+}
+
+/// Binds an ephemeral local port and immediately releases it, so each run gets a host port the
+/// OS considers free instead of colliding with a leaked or concurrently-running container on a
+/// hardcoded port.
+async fn free_local_port() -> Result<u16, bollard::errors::Error> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(|err| bollard::errors::Error::IOError { err })?;
+    listener
+        .local_addr()
+        .map(|addr| addr.port())
+        .map_err(|err| bollard::errors::Error::IOError { err })
+}
+
+async fn command_run_docker(
+    run_matches: &ArgMatches,
+    _docker_matches: &ArgMatches,
+) -> Result<(), bollard::errors::Error> {
     let docker = bollard::Docker::connect_with_local_defaults()?;
-    docker.create_container(Some(bollard::container::CreateContainerOptions{
+    let container = ValkeyContainer::start(docker).await?;
 
-        ..Default::default()
-    }), bollard::container::Config{
+    let workload = WorkloadConfig::from_matches(run_matches);
+    let languages = selected_languages(run_matches);
 
-        ..Default::default()
-    }).await?;
+    // Tear the container down regardless of whether the benchmarks themselves succeeded, errored,
+    // or panicked — a leaked container would permanently occupy its name/port for later runs.
+    let outcome = std::panic::AssertUnwindSafe(run_all_languages(
+        &languages,
+        "127.0.0.1",
+        container.port,
+        &workload,
+    ))
+    .catch_unwind()
+    .await;
+    container.teardown().await;
+    let results = match outcome {
+        Ok(results) => results,
+        Err(panic) => std::panic::resume_unwind(panic),
+    };
 
+    report_results(run_matches, results);
     Ok(())
 }
 
 async fn command_run_standalone(run_matches: &ArgMatches, standalone_matches: &ArgMatches) {
-    let mut csharp = run_matches.get_flag("csharp");
-    let mut java = run_matches.get_flag("java");
-    let mut python = run_matches.get_flag("python");
-    let mut rust = run_matches.get_flag("rust");
-    let mut nodejs = run_matches.get_flag("nodejs");
-    let mut go = run_matches.get_flag("go");
-    if !csharp && !java && !python && !rust && !nodejs && !go {
-        csharp = true;
-        java = true;
-        python = true;
-        rust = true;
-        nodejs = true;
-        go = true;
+    let host = standalone_matches.get_one::<String>("HOST").unwrap().clone();
+    let port: u16 = standalone_matches
+        .get_one::<String>("PORT")
+        .map(|p| p.parse().expect("PORT must be a valid port number"))
+        .unwrap_or(6379);
+
+    let workload = WorkloadConfig::from_matches(run_matches);
+    let languages = selected_languages(run_matches);
+    let results = run_all_languages(&languages, &host, port, &workload).await;
+    report_results(run_matches, results);
+}
+
+async fn run_all_languages(
+    languages: &[&str],
+    host: &str,
+    port: u16,
+    workload: &WorkloadConfig,
+) -> Vec<LanguageResult> {
+    let mut results = Vec::with_capacity(languages.len());
+    for language in languages {
+        match run_language_benchmark(language, host, port, workload).await {
+            Ok(result) => results.push(result),
+            Err(e) => eprintln!("{language} benchmark failed: {e}"),
+        }
+    }
+    results
+}
+
+/// Shells out to `benchmarks/<language>/run-benchmark.sh`, passing the workload as CLI flags,
+/// and parses its single-line `key=value` summary (`p50_ms`, `p90_ms`, `p99_ms`, `throughput_rps`)
+/// from stdout. Each language's benchmark binary owns its own client setup; this harness only
+/// orchestrates the run and aggregates the result.
+async fn run_language_benchmark(
+    language: &str,
+    host: &str,
+    port: u16,
+    workload: &WorkloadConfig,
+) -> Result<LanguageResult, std::io::Error> {
+    let script = format!("benchmarks/{language}/run-benchmark.sh");
+    let start = Instant::now();
+    let output = TokioCommand::new(&script)
+        .arg("--host")
+        .arg(host)
+        .arg("--port")
+        .arg(port.to_string())
+        .arg("--clients")
+        .arg(workload.clients.to_string())
+        .arg("--tasks")
+        .arg(workload.concurrent_tasks.to_string())
+        .arg("--data-size")
+        .arg(workload.data_size.to_string())
+        .arg("--command-mix")
+        .arg(&workload.command_mix)
+        .arg("--requests")
+        .arg(workload.total_requests.to_string())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .output()
+        .await?;
+    let duration = start.elapsed();
+
+    if !output.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!("{script} exited with status {}", output.status),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut p50_ms = 0.0;
+    let mut p90_ms = 0.0;
+    let mut p99_ms = 0.0;
+    let mut throughput_rps = None;
+    for pair in stdout.split_whitespace() {
+        if let Some((key, value)) = pair.split_once('=') {
+            let parsed: f64 = value.parse().unwrap_or(0.0);
+            match key {
+                "p50_ms" => p50_ms = parsed,
+                "p90_ms" => p90_ms = parsed,
+                "p99_ms" => p99_ms = parsed,
+                "throughput_rps" => throughput_rps = Some(parsed),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(LanguageResult {
+        language: language.to_string(),
+        host: host.to_string(),
+        port,
+        total_requests: workload.total_requests,
+        duration_secs: duration.as_secs_f64(),
+        throughput_rps: throughput_rps.unwrap_or(
+            workload.total_requests as f64 / duration.as_secs_f64().max(f64::EPSILON),
+        ),
+        p50_ms,
+        p90_ms,
+        p99_ms,
+    })
+}
+
+fn report_results(run_matches: &ArgMatches, results: Vec<LanguageResult>) {
+    let format = run_matches.get_one::<String>("format").unwrap();
+    if format == "json" {
+        for result in &results {
+            match serde_json::to_string(result) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("Failed to serialize result for {}: {e}", result.language),
+            }
+        }
+        return;
+    }
+
+    println!(
+        "{:<10} {:>12} {:>10} {:>10} {:>10} {:>10}",
+        "language", "requests", "p50 (ms)", "p90 (ms)", "p99 (ms)", "req/s"
+    );
+    for result in &results {
+        println!(
+            "{:<10} {:>12} {:>10.2} {:>10.2} {:>10.2} {:>10.1}",
+            result.language,
+            result.total_requests,
+            result.p50_ms,
+            result.p90_ms,
+            result.p99_ms,
+            result.throughput_rps,
+        );
     }
-    todo!()
 }
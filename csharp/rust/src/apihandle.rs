@@ -1,35 +1,520 @@
+use crate::compression::{self, CompressionConfig};
 use glide_core::client::{Client, ConnectionError};
 use glide_core::ConnectionRequest;
+use rand::Rng;
 use redis::cluster_routing::RoutingInfo;
-use redis::{Cmd, RedisError, RedisWrite, ToRedisArgs, Value};
+use redis::{Cmd, ErrorKind, PushInfo, RedisError, RedisWrite, ToRedisArgs, Value};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+/// Hex-encodes the SHA1 of `script`, matching the digest Redis/Valkey uses to key `SCRIPT LOAD`
+/// and `EVALSHA`.
+fn sha1_hex(script: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(script);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// A server replies with `NOSCRIPT` when the SHA passed to `EVALSHA` isn't cached on that node;
+/// that's the one case `invoke_script` can recover from by resending the body via `EVAL`.
+fn is_noscript_error(err: &RedisError) -> bool {
+    err.code() == Some("NOSCRIPT")
+}
+
+/// Configures how [`Handle::command`] retries a single command after a transient failure.
+///
+/// `max_attempts` counts the initial try, so `1` disables retrying entirely. Delays grow
+/// exponentially from `base_delay`, are capped at `max_delay`, and use full jitter (a delay
+/// drawn uniformly from `0..=capped_delay`) to avoid retry storms against the same node.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let exponential = self.base_delay.saturating_mul(1u32 << attempt.min(16));
+        let capped = exponential.min(self.max_delay);
+        let jittered_ms = rand::thread_rng().gen_range(0..=capped.as_millis() as u64);
+        Duration::from_millis(jittered_ms)
+    }
+}
+
+/// A per-call override of [`Handle::command`]'s retry behavior, modeled as a retry count plus a
+/// "slow-command" deadline: each attempt gets `slow_timeout` to complete, and after
+/// `terminate_after` consecutive attempts overrun that deadline the in-flight attempt is
+/// terminated and a timeout error is reported, regardless of `retries` remaining.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PerCallPolicy {
+    pub retries: u32,
+    pub slow_timeout: Option<Duration>,
+    pub terminate_after: u32,
+}
+
+/// Commands whose side effects are not safe to apply twice — a counter bump, a list push, a
+/// one-shot pop, a script invocation of unknown content. Resending one of these on a failure
+/// that may have already reached the server (a dropped connection, a timeout) risks duplicating
+/// its effect, so these are only retried when the failure is provable to have happened before
+/// the command was sent at all; see [`is_retryable`].
+const NON_IDEMPOTENT_COMMANDS: &[&str] = &[
+    "INCR",
+    "INCRBY",
+    "INCRBYFLOAT",
+    "DECR",
+    "DECRBY",
+    "APPEND",
+    "SETRANGE",
+    "GETSET",
+    "GETDEL",
+    "LPUSH",
+    "RPUSH",
+    "LPUSHX",
+    "RPUSHX",
+    "LPOP",
+    "RPOP",
+    "SPOP",
+    "LMOVE",
+    "BLMOVE",
+    "RPOPLPUSH",
+    "BRPOPLPUSH",
+    "XADD",
+    "PUBLISH",
+    "SPUBLISH",
+    "EVAL",
+    "EVALSHA",
+    "FCALL",
+];
+
+/// Extracts a command's name (its first argument) as an uppercase `String`, or `None` if the
+/// command has no arguments or its first argument isn't a plain string.
+fn command_name(cmd: &Cmd) -> Option<String> {
+    match cmd.args_iter().next()? {
+        redis::Arg::Simple(bytes) => std::str::from_utf8(bytes).ok().map(str::to_ascii_uppercase),
+        redis::Arg::Cursor => None,
+    }
+}
+
+/// Whether `cmd` is safe to resend blindly after an ambiguous failure. Unrecognized commands
+/// default to idempotent, since [`NON_IDEMPOTENT_COMMANDS`] is a denylist of known-dangerous
+/// cases rather than an exhaustive classification of every command.
+fn is_idempotent(cmd: &Cmd) -> bool {
+    match command_name(cmd) {
+        Some(name) => !NON_IDEMPOTENT_COMMANDS.contains(&name.as_str()),
+        None => true,
+    }
+}
+
+/// Whether `err` proves a command never reached the server, making a resend safe even for a
+/// non-idempotent command. A refused connection means the attempt failed before any bytes were
+/// written; a dropped connection or a timeout, by contrast, can legitimately happen *after* the
+/// server already executed and applied the command, so those aren't treated as provably pre-send.
+fn is_provably_pre_send(err: &RedisError) -> bool {
+    err.is_connection_refusal()
+}
+
+/// Classifies whether a `RedisError` is transient and safe to retry against `cmd`.
+///
+/// Redirections (`MOVED`/`ASK`/`TRYAGAIN`) and a node still `LOADING` its dataset are always
+/// retryable: in both cases the node that answered never executed the command. Connection drops,
+/// IO errors, and timeouts are retryable for idempotent commands; for non-idempotent commands
+/// (see [`NON_IDEMPOTENT_COMMANDS`]) they're only retried when [`is_provably_pre_send`] confirms
+/// the command never reached the server. Deterministic command errors (e.g. `WRONGTYPE`, syntax
+/// errors) are never retryable, since retrying them would just fail identically.
+fn is_retryable(err: &RedisError, cmd: &Cmd) -> bool {
+    if matches!(
+        err.kind(),
+        ErrorKind::Moved | ErrorKind::Ask | ErrorKind::TryAgain | ErrorKind::BusyLoadingError
+    ) {
+        return true;
+    }
+    let transient =
+        err.is_timeout() || err.is_io_error() || err.is_connection_dropped() || err.is_connection_refusal();
+    if !transient {
+        return false;
+    }
+    is_idempotent(cmd) || is_provably_pre_send(err)
+}
+
+/// Runs `action` until it succeeds, a non-retryable error is returned, or `policy.max_attempts`
+/// is exhausted, sleeping with exponential backoff and jitter between attempts. `cmd` is used
+/// only to classify retryability (see [`is_retryable`]); `action` is responsible for actually
+/// sending it.
+async fn retry_with_policy<F, Fut>(
+    policy: &RetryPolicy,
+    cmd: &Cmd,
+    mut action: F,
+) -> Result<Value, RedisError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<Value, RedisError>>,
+{
+    let mut attempt = 0u32;
+    loop {
+        match action().await {
+            Ok(d) => return Ok(d),
+            Err(e) => {
+                attempt += 1;
+                if attempt >= policy.max_attempts || !is_retryable(&e, cmd) {
+                    return Err(e);
+                }
+                logger_core::log_trace(
+                    "csharp_ffi::Handle",
+                    format!("Retrying after transient error (attempt {}): {:?}", attempt, e),
+                );
+                tokio::time::sleep(policy.delay_for_attempt(attempt)).await;
+            }
+        }
+    }
+}
+
+/// Appends `param` to `cmd`, transparently compressing `String`/`Bytes` payloads (including ones
+/// nested inside a `KeyValueArray`, e.g. an HSET/XADD field value) that exceed `compression`'s
+/// threshold instead of writing them as plain bulk strings.
+///
+/// `is_key` must be `true` for a command's leading key argument: compressing it would silently
+/// rewrite the key into the compression frame, so the data ends up stored/looked-up under a
+/// mangled name no other client (or `SCAN`/`KEYS`/TTL tooling) would recognize. This only protects
+/// the first argument, so multi-key commands (e.g. `MSET`, `MGET`) still risk compressing their
+/// second and later keys if those happen to exceed the threshold — keep such keys under
+/// `threshold` bytes.
+fn arg_with_compression(
+    cmd: &mut Cmd,
+    param: &CommandParameter,
+    compression: &CompressionConfig,
+    is_key: bool,
+) {
+    if let CommandParameter::KeyValueArray(pairs) = param {
+        for (key, value) in pairs {
+            cmd.arg(key);
+            arg_with_compression(cmd, value, compression, false);
+        }
+        return;
+    }
+    if is_key {
+        cmd.arg(param);
+        return;
+    }
+    let framed = match param {
+        CommandParameter::String(value) => compression.compress(value.as_bytes()),
+        CommandParameter::Bytes(value) => compression.compress(value),
+        _ => None,
+    };
+    match framed {
+        Some(frame) => {
+            cmd.arg(frame);
+        }
+        None => {
+            cmd.arg(param);
+        }
+    }
+}
+
+/// Unwraps a pipeline's raw reply into the per-command `Vec<Value>` the FFI layer expects,
+/// decompressing any compressed bulk strings along the way.
+///
+/// A command that failed inside a non-atomic batch comes back as an error *entry* within the
+/// reply array, not as an `Err` from `send_pipeline` itself — only a transport-level failure
+/// (a dropped connection, a malformed reply) surfaces as `Err` there. So this never discards or
+/// collapses entries: every position in the returned `Vec` lines up with `commands`, whatever
+/// each entry's value turned out to be.
+fn unwrap_pipeline_result(compression: &CompressionConfig, result: Value) -> Vec<Value> {
+    match compression::inflate_if_enabled(compression, result) {
+        Value::Array(values) => values,
+        other => vec![other],
+    }
+}
+
+/// Source of [`Handle::identity_key`] values. A monotonically increasing counter, unlike a
+/// pointer address, can never be reused once a `Handle` is dropped, so a stale cache/registry
+/// entry left behind by a missed cleanup can never collide with a later, unrelated connection.
+static NEXT_HANDLE_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(1);
 
 #[derive(Clone)]
 pub(crate) struct Handle {
     client: Client,
+    retry_policy: RetryPolicy,
+    compression: CompressionConfig,
+    /// Holds the receiving half of the push-message channel handed to `Client::new` until the
+    /// first `csharp_subscribe` call claims it for its dispatcher task; `None` afterward.
+    push_receiver: Arc<Mutex<Option<UnboundedReceiver<PushInfo>>>>,
+    /// Caches script bodies by their SHA1 so `invoke_script` can fall back from EVALSHA to EVAL
+    /// on a NOSCRIPT error without the caller having to resend the body.
+    script_cache: Arc<std::sync::RwLock<HashMap<String, Vec<u8>>>>,
+    /// See [`Handle::identity_key`].
+    identity: usize,
 }
 
 impl Handle {
-    pub async fn create(request: ConnectionRequest) -> Result<Self, ConnectionError> {
-        let client = Client::new(request, None).await?;
-        Ok(Self { client })
+    pub async fn create(
+        request: ConnectionRequest,
+        retry_policy: RetryPolicy,
+        compression: CompressionConfig,
+    ) -> Result<Self, ConnectionError> {
+        let (push_sender, push_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let client = Client::new(request, Some(push_sender)).await?;
+        Ok(Self {
+            client,
+            retry_policy,
+            compression,
+            push_receiver: Arc::new(Mutex::new(Some(push_receiver))),
+            script_cache: Arc::new(std::sync::RwLock::new(HashMap::new())),
+            identity: NEXT_HANDLE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        })
+    }
+
+    /// Takes ownership of the push-message receiver, if nobody has claimed it yet. Used by the
+    /// subscription dispatcher, which must be the sole reader of incoming push messages.
+    pub fn take_push_receiver(&self) -> Option<UnboundedReceiver<PushInfo>> {
+        self.push_receiver.lock().unwrap().take()
+    }
+
+    /// Returns a stable key identifying the underlying connection, used to key the subscription
+    /// registry and capability cache independently of how many `Handle` clones (e.g. across FFI
+    /// calls) exist. Unique for the life of the process — never reused after the `Handle` is
+    /// freed — so callers must clean up their keyed entries explicitly (see
+    /// `csharp_free_client_handle`) rather than relying on the key becoming unreachable.
+    pub fn identity_key(&self) -> usize {
+        self.identity
+    }
+
+    /// The handle's compression settings, so push-message delivery (which bypasses
+    /// [`Handle::command`]'s reply path) can inflate compressed payloads the same way.
+    pub fn compression(&self) -> CompressionConfig {
+        self.compression
     }
 
     pub async fn command(
         &self,
-        mut cmd: Cmd,
+        cmd: Cmd,
+        args: &[CommandParameter],
+        routing: Option<RoutingInfo>,
+    ) -> Result<Value, RedisError> {
+        let result = retry_with_policy(&self.retry_policy, &cmd, || {
+            let mut cmd = cmd.clone();
+            for (i, arg) in args.iter().enumerate() {
+                arg_with_compression(&mut cmd, arg, &self.compression, i == 0);
+            }
+            let mut clone = self.client.clone();
+            let routing = routing.clone();
+            async move {
+                logger_core::log_trace("csharp_ffi::Handle", format!("Sending command {:?}", cmd));
+                clone.send_command(&cmd, routing).await
+            }
+        })
+        .await?;
+        Ok(compression::inflate_if_enabled(&self.compression, result))
+    }
+
+    /// Like [`Handle::command`], but lets a single call override the handle-wide retry policy
+    /// with a "slow-command" deadline: each attempt gets `call_policy.slow_timeout` to complete,
+    /// and after `call_policy.terminate_after` consecutive deadline overruns the attempt is
+    /// terminated and a timeout error is returned even if retries remain. `None` falls back to
+    /// the handle's configured `RetryPolicy`.
+    pub async fn command_with_policy(
+        &self,
+        cmd: Cmd,
         args: &[CommandParameter],
         routing: Option<RoutingInfo>,
+        call_policy: Option<PerCallPolicy>,
     ) -> Result<Value, RedisError> {
+        let Some(policy) = call_policy else {
+            return self.command(cmd, args, routing).await;
+        };
+        let max_attempts = policy.retries.saturating_add(1);
+        let mut consecutive_overruns = 0u32;
+        let mut attempt = 0u32;
+        loop {
+            let mut attempt_cmd = cmd.clone();
+            for (i, arg) in args.iter().enumerate() {
+                arg_with_compression(&mut attempt_cmd, arg, &self.compression, i == 0);
+            }
+            let mut clone = self.client.clone();
+            let attempt_routing = routing.clone();
+            logger_core::log_trace(
+                "csharp_ffi::Handle",
+                format!("Sending command with per-call policy {:?}", attempt_cmd),
+            );
+            let send = async move { clone.send_command(&attempt_cmd, attempt_routing).await };
+            let outcome = match policy.slow_timeout {
+                Some(deadline) => tokio::time::timeout(deadline, send).await,
+                None => Ok(send.await),
+            };
+            match outcome {
+                Ok(Ok(value)) => {
+                    return Ok(compression::inflate_if_enabled(&self.compression, value))
+                }
+                Ok(Err(e)) => {
+                    consecutive_overruns = 0;
+                    attempt += 1;
+                    if attempt >= max_attempts || !is_retryable(&e, &cmd) {
+                        return Err(e);
+                    }
+                    tokio::time::sleep(self.retry_policy.delay_for_attempt(attempt)).await;
+                }
+                Err(_elapsed) => {
+                    let timeout_err = RedisError::from((
+                        ErrorKind::IoError,
+                        "Command exceeded its slow-command deadline",
+                    ));
+                    // A slow-command timeout is exactly the ambiguous case `is_retryable` guards
+                    // against: the server may have already executed the command before the
+                    // deadline fired, so a non-idempotent command can't be blindly resent here
+                    // any more than it can after the plain transport-error path above.
+                    if !is_idempotent(&cmd) && !is_provably_pre_send(&timeout_err) {
+                        return Err(timeout_err);
+                    }
+                    consecutive_overruns += 1;
+                    attempt += 1;
+                    if consecutive_overruns >= policy.terminate_after || attempt >= max_attempts {
+                        return Err(timeout_err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Issues a batch of commands in a single round trip.
+    ///
+    /// When `atomic` is set, the batch is wrapped in MULTI/EXEC as a transaction;
+    /// otherwise the commands are issued as a plain pipeline. The returned
+    /// `Vec<Value>` is aligned with `commands` regardless of the mode.
+    pub async fn pipeline(
+        &self,
+        commands: Vec<(Cmd, Vec<CommandParameter>)>,
+        atomic: bool,
+        routing: Option<RoutingInfo>,
+    ) -> Result<Vec<Value>, RedisError> {
         let mut clone = self.client.clone();
-        for arg in args {
-            cmd.arg(arg);
+        let mut pipeline = redis::pipe();
+        if atomic {
+            pipeline.atomic();
         }
-        logger_core::log_trace("csharp_ffi::Handle", format!("Sending command {:?}", cmd));
-        let result = match clone.send_command(&cmd, routing).await {
-            Ok(d) => d,
-            Err(e) => return Err(e),
-        };
-        Ok(result)
+        for (mut cmd, args) in commands {
+            for (i, arg) in args.iter().enumerate() {
+                arg_with_compression(&mut cmd, arg, &self.compression, i == 0);
+            }
+            pipeline.add_command(cmd);
+        }
+        logger_core::log_trace(
+            "csharp_ffi::Handle",
+            format!("Sending pipeline {:?}", pipeline),
+        );
+        let result = clone.send_pipeline(&pipeline, routing).await?;
+        Ok(unwrap_pipeline_result(&self.compression, result))
+    }
+
+    /// Caches `script`'s body under its SHA1 so a later `invoke_script` can reach it after a
+    /// NOSCRIPT fallback, and returns that SHA1 for the caller to pass to `invoke_script`.
+    pub fn load_script(&self, script: &[u8]) -> String {
+        let sha = sha1_hex(script);
+        self.script_cache
+            .write()
+            .unwrap()
+            .insert(sha.clone(), script.to_vec());
+        sha
+    }
+
+    /// Evaluates a script body by caching it under its SHA1 and then running it through
+    /// `invoke_script`: `EVALSHA` first, falling back to `EVAL` with the body on `NOSCRIPT`.
+    /// Subsequent calls can use `invoke_script` with the SHA1 instead of resending the body.
+    pub async fn eval_script(
+        &self,
+        script: &[u8],
+        keys: Vec<CommandParameter>,
+        args: Vec<CommandParameter>,
+        routing: Option<RoutingInfo>,
+    ) -> Result<Value, RedisError> {
+        let sha = self.load_script(script);
+        self.invoke_script(&sha, keys, args, routing).await
+    }
+
+    /// Invokes a previously loaded script by SHA1 via `EVALSHA`, transparently falling back to
+    /// `EVAL` with the cached body (and repopulating the node) on a `NOSCRIPT` error.
+    pub async fn invoke_script(
+        &self,
+        sha: &str,
+        keys: Vec<CommandParameter>,
+        args: Vec<CommandParameter>,
+        routing: Option<RoutingInfo>,
+    ) -> Result<Value, RedisError> {
+        let keys_len = keys.len();
+        let mut combined = keys;
+        combined.extend(args);
+
+        invoke_script_with_actions(
+            sha,
+            &self.script_cache,
+            || {
+                let mut evalsha = Cmd::new();
+                evalsha.arg("EVALSHA").arg(sha).arg(keys_len);
+                let combined = &combined;
+                let routing = routing.clone();
+                async move { self.command(evalsha, combined, routing).await }
+            },
+            |body| {
+                let mut eval = Cmd::new();
+                eval.arg("EVAL").arg(body).arg(keys_len);
+                let combined = &combined;
+                let routing = routing.clone();
+                async move { self.command(eval, combined, routing).await }
+            },
+        )
+        .await
+    }
+}
+
+/// Core of [`Handle::invoke_script`], with the EVALSHA/EVAL sends taken as injectable actions so
+/// the NOSCRIPT-triggers-EVAL-fallback logic can be unit-tested without a live server, the same
+/// way [`retry_with_policy`] is tested via an injectable `action` closure.
+async fn invoke_script_with_actions<F1, Fut1, F2, Fut2>(
+    sha: &str,
+    script_cache: &std::sync::RwLock<HashMap<String, Vec<u8>>>,
+    try_evalsha: F1,
+    try_eval: F2,
+) -> Result<Value, RedisError>
+where
+    F1: FnOnce() -> Fut1,
+    Fut1: Future<Output = Result<Value, RedisError>>,
+    F2: FnOnce(Vec<u8>) -> Fut2,
+    Fut2: Future<Output = Result<Value, RedisError>>,
+{
+    match try_evalsha().await {
+        Ok(value) => Ok(value),
+        Err(e) if is_noscript_error(&e) => {
+            let body = script_cache
+                .read()
+                .unwrap()
+                .get(sha)
+                .cloned()
+                .ok_or_else(|| {
+                    RedisError::from((
+                        ErrorKind::ClientError,
+                        "Unknown script SHA; call load_script or eval_script first",
+                    ))
+                })?;
+            try_eval(body).await
+        }
+        Err(e) => Err(e),
     }
 }
 
@@ -46,6 +531,7 @@ pub enum CommandParameter {
     Float32(f32),
     Float64(f64),
     String(String),
+    Bytes(Vec<u8>),
     BoolArray(Vec<bool>),
     Int8Array(Vec<i8>),
     Uint8Array(Vec<u8>),
@@ -77,6 +563,7 @@ impl ToRedisArgs for CommandParameter {
             CommandParameter::Float32(value) => value.write_redis_args(out),
             CommandParameter::Float64(value) => value.write_redis_args(out),
             CommandParameter::String(value) => value.write_redis_args(out),
+            CommandParameter::Bytes(value) => out.write_arg(value),
             CommandParameter::BoolArray(value) => value.write_redis_args(out),
             CommandParameter::Int8Array(value) => value.write_redis_args(out),
             CommandParameter::Uint8Array(value) => value.write_redis_args(out),
@@ -88,9 +575,481 @@ impl ToRedisArgs for CommandParameter {
             CommandParameter::Uint64Array(value) => value.write_redis_args(out),
             CommandParameter::Float32Array(value) => value.write_redis_args(out),
             CommandParameter::Float64Array(value) => value.write_redis_args(out),
-            CommandParameter::KeyValueArray(_value) => {
-                todo!("Implement KeyValueArray")
+            CommandParameter::KeyValueArray(value) => {
+                for (key, value) in value {
+                    key.write_redis_args(out);
+                    value.write_redis_args(out);
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn flatten(param: &CommandParameter) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        param.write_redis_args(&mut out);
+        out
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_succeeds_after_n_transient_failures() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("GET").arg("key");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 {
+                    Err(RedisError::from(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionReset,
+                        "transient",
+                    )))
+                } else {
+                    Ok(Value::Okay)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_gives_up_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("GET").arg("key");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                Err::<Value, _>(RedisError::from(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "transient",
+                )))
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_does_not_retry_non_retryable_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("GET").arg("key");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                Err::<Value, _>(RedisError::from((
+                    ErrorKind::TypeError,
+                    "WRONGTYPE",
+                )))
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_does_not_resend_non_idempotent_command_on_ambiguous_failure() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("INCR").arg("counter");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                // A dropped connection could have reached the server after applying INCR, so
+                // this must not be retried.
+                Err::<Value, _>(RedisError::from(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionReset,
+                    "transient",
+                )))
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_resends_non_idempotent_command_on_connection_refusal() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("INCR").arg("counter");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 2 {
+                    // Connection refusal proves the command never reached the server, so it's
+                    // safe to resend even though INCR isn't idempotent.
+                    Err(RedisError::from(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionRefused,
+                        "refused",
+                    )))
+                } else {
+                    Ok(Value::Okay)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_always_retries_redirections_for_non_idempotent_commands() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("INCR").arg("counter");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 2 {
+                    // MOVED means this node never executed the command, regardless of idempotency.
+                    Err(RedisError::from((ErrorKind::Moved, "MOVED")))
+                } else {
+                    Ok(Value::Okay)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn retry_with_policy_always_retries_loading_for_non_idempotent_commands() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        };
+        let mut cmd = Cmd::new();
+        cmd.arg("INCR").arg("counter");
+        let attempts = Cell::new(0u32);
+        let result = retry_with_policy(&policy, &cmd, || {
+            let attempts = &attempts;
+            async move {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 2 {
+                    // LOADING means this node never executed the command, regardless of idempotency.
+                    Err(RedisError::from((ErrorKind::BusyLoadingError, "LOADING")))
+                } else {
+                    Ok(Value::Okay)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+        assert_eq!(attempts.get(), 2);
+    }
+
+    #[test]
+    fn unwrap_pipeline_result_keeps_every_entry_when_one_command_failed() {
+        let compression = CompressionConfig::default();
+        // Simulates a non-atomic batch where the second command failed: the reply array still
+        // carries all three entries in order, and unwrapping must not discard or reorder any of
+        // them just because one represents a failure.
+        let result = Value::Array(vec![
+            Value::Okay,
+            Value::BulkString(b"ERR wrong number of arguments".to_vec()),
+            Value::Int(42),
+        ]);
+        let values = unwrap_pipeline_result(&compression, result);
+        assert_eq!(
+            values,
+            vec![
+                Value::Okay,
+                Value::BulkString(b"ERR wrong number of arguments".to_vec()),
+                Value::Int(42),
+            ]
+        );
+    }
+
+    #[test]
+    fn unwrap_pipeline_result_wraps_a_non_array_reply_as_a_single_entry() {
+        let compression = CompressionConfig::default();
+        assert_eq!(
+            unwrap_pipeline_result(&compression, Value::Okay),
+            vec![Value::Okay]
+        );
+    }
+
+    #[test]
+    fn is_idempotent_flags_known_non_idempotent_commands() {
+        let mut incr = Cmd::new();
+        incr.arg("INCR").arg("counter");
+        assert!(!is_idempotent(&incr));
+
+        let mut get = Cmd::new();
+        get.arg("GET").arg("key");
+        assert!(is_idempotent(&get));
+    }
+
+    #[test]
+    fn key_value_array_empty_writes_nothing() {
+        let param = CommandParameter::KeyValueArray(vec![]);
+        assert_eq!(flatten(&param), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn key_value_array_scalar_values_interleave_key_and_value() {
+        let param = CommandParameter::KeyValueArray(vec![
+            ("field1".to_string(), CommandParameter::String("hello".to_string())),
+            ("field2".to_string(), CommandParameter::Int64(42)),
+        ]);
+        assert_eq!(
+            flatten(&param),
+            vec![
+                b"field1".to_vec(),
+                b"hello".to_vec(),
+                b"field2".to_vec(),
+                b"42".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_value_array_array_value_expands_in_place() {
+        let param = CommandParameter::KeyValueArray(vec![(
+            "field".to_string(),
+            CommandParameter::Int32Array(vec![1, 2, 3]),
+        )]);
+        assert_eq!(
+            flatten(&param),
+            vec![
+                b"field".to_vec(),
+                b"1".to_vec(),
+                b"2".to_vec(),
+                b"3".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_round_trips_embedded_nul_and_high_bytes() {
+        let raw = vec![0x00, 0x01, 0xff, 0x00, 0x7f, 0xfe];
+        let param = CommandParameter::Bytes(raw.clone());
+        assert_eq!(flatten(&param), vec![raw]);
+    }
+
+    #[test]
+    fn arg_with_compression_compresses_a_value_nested_in_a_key_value_array() {
+        let compression = CompressionConfig {
+            mode: crate::compression::CompressionMode::Lz4,
+            threshold: 8,
+        };
+        let large_value = "x".repeat(64);
+        let param = CommandParameter::KeyValueArray(vec![(
+            "field".to_string(),
+            CommandParameter::String(large_value.clone()),
+        )]);
+        let mut cmd = Cmd::new();
+        arg_with_compression(&mut cmd, &param, &compression, false);
+        let args: Vec<Vec<u8>> = cmd.args_iter().map(|arg| match arg {
+            redis::Arg::Simple(bytes) => bytes.to_vec(),
+            redis::Arg::Cursor => Vec::new(),
+        }).collect();
+        assert_eq!(args[0], b"field".to_vec());
+        // The framed value must differ from the plain bytes (it was compressed), not just be
+        // copied through untouched the way it was before KeyValueArray was recursed into.
+        assert_ne!(args[1], large_value.as_bytes().to_vec());
+        assert_eq!(
+            compression::inflate_if_enabled(&compression, Value::BulkString(args[1].clone())),
+            Value::BulkString(large_value.into_bytes())
+        );
+    }
+
+    #[test]
+    fn arg_with_compression_never_compresses_the_leading_key_argument() {
+        let compression = CompressionConfig {
+            mode: crate::compression::CompressionMode::Lz4,
+            threshold: 8,
+        };
+        let large_key = "k".repeat(64);
+        let large_value = "v".repeat(64);
+        let mut cmd = Cmd::new();
+        arg_with_compression(
+            &mut cmd,
+            &CommandParameter::String(large_key.clone()),
+            &compression,
+            true,
+        );
+        arg_with_compression(
+            &mut cmd,
+            &CommandParameter::String(large_value.clone()),
+            &compression,
+            false,
+        );
+        let args: Vec<Vec<u8>> = cmd
+            .args_iter()
+            .map(|arg| match arg {
+                redis::Arg::Simple(bytes) => bytes.to_vec(),
+                redis::Arg::Cursor => Vec::new(),
+            })
+            .collect();
+        // The key must be written verbatim even though it exceeds the threshold, so a lookup by
+        // another client still finds it; the trailing value is still compressed as usual.
+        assert_eq!(args[0], large_key.into_bytes());
+        assert_ne!(args[1], large_value.as_bytes().to_vec());
+    }
+
+    #[test]
+    fn sha1_hex_matches_a_known_vector() {
+        // echo -n "return 1" | sha1sum
+        assert_eq!(
+            sha1_hex(b"return 1"),
+            "e0e1f9fabfc9d4800c877a703b823ac0578ff8db"
+        );
+    }
+
+    #[test]
+    fn sha1_hex_of_empty_input_matches_the_well_known_empty_sha1() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn is_noscript_error_matches_only_the_noscript_error_code() {
+        assert!(is_noscript_error(&RedisError::from((
+            ErrorKind::NoScriptError,
+            "NOSCRIPT",
+        ))));
+        assert!(!is_noscript_error(&RedisError::from((
+            ErrorKind::TypeError,
+            "WRONGTYPE",
+        ))));
+    }
+
+    #[tokio::test]
+    async fn invoke_script_with_actions_returns_the_evalsha_result_when_it_succeeds() {
+        let cache = std::sync::RwLock::new(HashMap::new());
+        let eval_calls = Cell::new(0u32);
+        let result = invoke_script_with_actions(
+            "deadbeef",
+            &cache,
+            || async { Ok(Value::Okay) },
+            |_body| {
+                eval_calls.set(eval_calls.get() + 1);
+                async { Ok(Value::Nil) }
+            },
+        )
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+        assert_eq!(eval_calls.get(), 0, "EVAL must not run when EVALSHA succeeds");
+    }
+
+    #[tokio::test]
+    async fn invoke_script_with_actions_falls_back_to_eval_on_noscript() {
+        let mut cache = HashMap::new();
+        cache.insert("deadbeef".to_string(), b"return 1".to_vec());
+        let cache = std::sync::RwLock::new(cache);
+        let result = invoke_script_with_actions(
+            "deadbeef",
+            &cache,
+            || async {
+                Err(RedisError::from((ErrorKind::NoScriptError, "NOSCRIPT")))
+            },
+            |body| async move {
+                assert_eq!(body, b"return 1".to_vec());
+                Ok(Value::Okay)
+            },
+        )
+        .await;
+        assert_eq!(result, Ok(Value::Okay));
+    }
+
+    #[tokio::test]
+    async fn invoke_script_with_actions_does_not_fall_back_on_a_non_noscript_error() {
+        let mut cache = HashMap::new();
+        cache.insert("deadbeef".to_string(), b"return 1".to_vec());
+        let cache = std::sync::RwLock::new(cache);
+        let eval_calls = Cell::new(0u32);
+        let result = invoke_script_with_actions(
+            "deadbeef",
+            &cache,
+            || async { Err::<Value, _>(RedisError::from((ErrorKind::TypeError, "WRONGTYPE"))) },
+            |_body| {
+                eval_calls.set(eval_calls.get() + 1);
+                async { Ok(Value::Okay) }
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(eval_calls.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn invoke_script_with_actions_errors_when_the_body_was_never_cached() {
+        let cache = std::sync::RwLock::new(HashMap::new());
+        let result = invoke_script_with_actions(
+            "unknown-sha",
+            &cache,
+            || async { Err(RedisError::from((ErrorKind::NoScriptError, "NOSCRIPT"))) },
+            |_body| async { Ok(Value::Okay) },
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn key_value_array_nested_key_value_array_recurses() {
+        let param = CommandParameter::KeyValueArray(vec![(
+            "outer".to_string(),
+            CommandParameter::KeyValueArray(vec![(
+                "inner".to_string(),
+                CommandParameter::String("value".to_string()),
+            )]),
+        )]);
+        assert_eq!(
+            flatten(&param),
+            vec![
+                b"outer".to_vec(),
+                b"inner".to_vec(),
+                b"value".to_vec(),
+            ]
+        );
+    }
+}
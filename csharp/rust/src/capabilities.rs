@@ -0,0 +1,233 @@
+use crate::apihandle::Handle;
+use redis::{Cmd, RedisError, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Which server implementation answered `INFO server`. `Unknown` means the response didn't
+/// contain a recognizable version line (e.g. the server errored or sent something unexpected).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Engine {
+    #[default]
+    Unknown,
+    Redis,
+    Valkey,
+}
+
+/// Feature groups inferred from the detected engine/version, so callers can feature-gate
+/// commands (pub-sub sharding, functions, client-side caching) without a failed round trip.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct FeatureFlags {
+    pub pubsub_sharding: bool,
+    pub functions: bool,
+    pub client_side_caching: bool,
+}
+
+impl FeatureFlags {
+    pub fn as_bits(&self) -> u32 {
+        let mut bits = 0u32;
+        if self.pubsub_sharding {
+            bits |= 1 << 0;
+        }
+        if self.functions {
+            bits |= 1 << 1;
+        }
+        if self.client_side_caching {
+            bits |= 1 << 2;
+        }
+        bits
+    }
+}
+
+/// Negotiated capabilities of a single connection: engine, version, RESP protocol level, and
+/// feature groups. Computed once at connect time and cached by [`detect_and_cache`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ServerCapabilities {
+    pub engine: Engine,
+    pub version: (u32, u32, u32),
+    pub resp_protocol: u8,
+    pub features: FeatureFlags,
+}
+
+fn parse_version(text: &str) -> (u32, u32, u32) {
+    let mut parts = text.trim().splitn(3, '.');
+    let major = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    (major, minor, patch)
+}
+
+/// Valkey reports both `redis_version` (for compatibility) and `valkey_version`; a `valkey_version`
+/// line being present is what distinguishes it from upstream Redis.
+fn parse_info(info: &str) -> (Engine, (u32, u32, u32)) {
+    let mut engine = Engine::Unknown;
+    let mut version = (0, 0, 0);
+    for line in info.lines() {
+        if let Some(v) = line.strip_prefix("valkey_version:") {
+            engine = Engine::Valkey;
+            version = parse_version(v);
+        } else if let Some(v) = line.strip_prefix("redis_version:") {
+            if engine == Engine::Unknown {
+                engine = Engine::Redis;
+            }
+            if version == (0, 0, 0) {
+                version = parse_version(v);
+            }
+        }
+    }
+    (engine, version)
+}
+
+fn features_for(version: (u32, u32, u32)) -> FeatureFlags {
+    FeatureFlags {
+        pubsub_sharding: version >= (7, 0, 0),
+        functions: version >= (7, 0, 0),
+        client_side_caching: version >= (6, 0, 0),
+    }
+}
+
+/// Queries `INFO server` for the engine/version and `HELLO` for the negotiated RESP protocol
+/// level, then derives the feature flags implied by that version.
+async fn detect(handle: &Handle) -> Result<ServerCapabilities, RedisError> {
+    let mut info_cmd = Cmd::new();
+    info_cmd.arg("INFO").arg("server");
+    let info = handle.command(info_cmd, &[], None).await?;
+    let info_text = match info {
+        Value::BulkString(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Value::SimpleString(s) => s,
+        _ => String::new(),
+    };
+    let (engine, version) = parse_info(&info_text);
+
+    let mut hello_cmd = Cmd::new();
+    hello_cmd.arg("HELLO");
+    let hello = handle.command(hello_cmd, &[], None).await?;
+    let resp_protocol = match hello {
+        Value::Map(pairs) => pairs
+            .into_iter()
+            .find_map(|(key, value)| match (key, value) {
+                (Value::BulkString(key), Value::Int(proto)) if key == b"proto" => {
+                    Some(proto as u8)
+                }
+                _ => None,
+            })
+            .unwrap_or(2),
+        _ => 2,
+    };
+
+    Ok(ServerCapabilities {
+        engine,
+        version,
+        resp_protocol,
+        features: features_for(version),
+    })
+}
+
+static CACHE: Mutex<Option<HashMap<usize, ServerCapabilities>>> = Mutex::new(None);
+
+/// Runs [`detect`] and caches the result under `handle_key` (see [`Handle::identity_key`]),
+/// overwriting any previous entry. Called once right after `Handle::create` succeeds.
+pub(crate) async fn detect_and_cache(
+    handle_key: usize,
+    handle: &Handle,
+) -> Result<ServerCapabilities, RedisError> {
+    let capabilities = detect(handle).await?;
+    CACHE
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(handle_key, capabilities.clone());
+    Ok(capabilities)
+}
+
+/// Cheap accessor for the capabilities cached by `detect_and_cache`, or `None` if detection
+/// hasn't completed (or failed) for this connection yet.
+pub(crate) fn cached(handle_key: usize) -> Option<ServerCapabilities> {
+    CACHE.lock().unwrap().as_ref()?.get(&handle_key).cloned()
+}
+
+/// Removes `handle_key`'s cached capabilities, if any. Must be called when the corresponding
+/// `Handle` is freed so the cache doesn't grow for the life of the process.
+pub(crate) fn forget(handle_key: usize) {
+    if let Some(cache) = CACHE.lock().unwrap().as_mut() {
+        cache.remove(&handle_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version_reads_major_minor_patch() {
+        assert_eq!(parse_version("7.2.5"), (7, 2, 5));
+    }
+
+    #[test]
+    fn parse_version_defaults_missing_components_to_zero() {
+        assert_eq!(parse_version("7"), (7, 0, 0));
+        assert_eq!(parse_version("7.2"), (7, 2, 0));
+    }
+
+    #[test]
+    fn parse_version_defaults_a_non_numeric_version_to_zero() {
+        assert_eq!(parse_version("not-a-number"), (0, 0, 0));
+    }
+
+    #[test]
+    fn parse_info_classifies_redis_when_only_redis_version_is_present() {
+        let info = "redis_version:7.0.5\r\nother_field:x\r\n";
+        assert_eq!(parse_info(info), (Engine::Redis, (7, 0, 5)));
+    }
+
+    #[test]
+    fn parse_info_classifies_valkey_and_prefers_its_version_when_both_lines_are_present() {
+        let info = "redis_version:7.0.0\r\nvalkey_version:8.1.2\r\n";
+        assert_eq!(parse_info(info), (Engine::Valkey, (8, 1, 2)));
+    }
+
+    #[test]
+    fn parse_info_returns_unknown_for_an_info_blob_without_a_version_line() {
+        assert_eq!(parse_info("some_field:x\r\n"), (Engine::Unknown, (0, 0, 0)));
+    }
+
+    #[test]
+    fn features_for_is_all_disabled_below_the_6_0_0_boundary() {
+        let features = features_for((5, 9, 9));
+        assert!(!features.pubsub_sharding);
+        assert!(!features.functions);
+        assert!(!features.client_side_caching);
+    }
+
+    #[test]
+    fn features_for_enables_client_side_caching_at_the_6_0_0_boundary_inclusive() {
+        let features = features_for((6, 0, 0));
+        assert!(features.client_side_caching);
+        assert!(!features.pubsub_sharding);
+        assert!(!features.functions);
+    }
+
+    #[test]
+    fn features_for_enables_sharding_and_functions_at_the_7_0_0_boundary_inclusive() {
+        let features = features_for((7, 0, 0));
+        assert!(features.pubsub_sharding);
+        assert!(features.functions);
+        assert!(features.client_side_caching);
+    }
+
+    #[test]
+    fn as_bits_sets_one_bit_per_enabled_feature() {
+        assert_eq!(FeatureFlags::default().as_bits(), 0);
+        let all_enabled = FeatureFlags {
+            pubsub_sharding: true,
+            functions: true,
+            client_side_caching: true,
+        };
+        assert_eq!(all_enabled.as_bits(), 0b111);
+        let only_functions = FeatureFlags {
+            pubsub_sharding: false,
+            functions: true,
+            client_side_caching: false,
+        };
+        assert_eq!(only_functions.as_bits(), 0b010);
+    }
+}
@@ -0,0 +1,196 @@
+use redis::Value;
+
+/// Marks a value as framed by [`CompressionConfig::compress`]. Values without this leading byte
+/// are assumed to come from a client that doesn't compress and are passed through untouched.
+const MAGIC_BYTE: u8 = 0xC5;
+const LZ4_ALGORITHM_ID: u8 = 1;
+const SNAPPY_ALGORITHM_ID: u8 = 2;
+
+/// Client-side compression algorithm applied to large bulk string/byte values before they are
+/// sent, and transparently reversed on values read back from the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum CompressionMode {
+    #[default]
+    None,
+    Lz4,
+    Snappy,
+}
+
+/// Opt-in compression settings stored on [`crate::apihandle::Handle`].
+///
+/// Only `String`/`Bytes` parameters whose length exceeds `threshold` are compressed; smaller
+/// values are left as plain bulk strings to avoid framing overhead dwarfing the payload.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CompressionConfig {
+    pub mode: CompressionMode,
+    pub threshold: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            mode: CompressionMode::None,
+            threshold: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Compresses `data` into a self-describing frame (magic byte, algorithm id, original
+    /// length, compressed bytes) when compression is enabled and `data` is large enough to be
+    /// worth framing. Returns `None` otherwise, in which case the caller should write `data`
+    /// as a plain bulk string.
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() <= self.threshold {
+            return None;
+        }
+        let (algorithm_id, compressed) = match self.mode {
+            CompressionMode::None => return None,
+            CompressionMode::Lz4 => (LZ4_ALGORITHM_ID, lz4_flex::compress(data)),
+            CompressionMode::Snappy => (
+                SNAPPY_ALGORITHM_ID,
+                snap::raw::Encoder::new()
+                    .compress_vec(data)
+                    .expect("snappy compression of an in-memory buffer cannot fail"),
+            ),
+        };
+        let mut frame = Vec::with_capacity(compressed.len() + 6);
+        frame.push(MAGIC_BYTE);
+        frame.push(algorithm_id);
+        frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&compressed);
+        Some(frame)
+    }
+}
+
+/// Inflates `data` if it carries the compression frame's magic header; values below the
+/// threshold or written by a non-compressing client pass through unchanged.
+fn decompress_frame(data: &[u8]) -> Vec<u8> {
+    if data.len() < 6 || data[0] != MAGIC_BYTE {
+        return data.to_vec();
+    }
+    let algorithm_id = data[1];
+    let original_len = u32::from_be_bytes([data[2], data[3], data[4], data[5]]) as usize;
+    let payload = &data[6..];
+    match algorithm_id {
+        LZ4_ALGORITHM_ID => {
+            lz4_flex::decompress(payload, original_len).unwrap_or_else(|_| data.to_vec())
+        }
+        SNAPPY_ALGORITHM_ID => snap::raw::Decoder::new()
+            .decompress_vec(payload)
+            .unwrap_or_else(|_| data.to_vec()),
+        _ => data.to_vec(),
+    }
+}
+
+/// Recursively walks a `Value` tree returned by the server, inflating any `BulkString` that
+/// carries a compression frame while leaving every other value untouched.
+///
+/// Only called when `config.mode` is not [`CompressionMode::None`] (see
+/// [`inflate_if_enabled`]): the magic byte is a single-byte heuristic with no checksum, so
+/// running it over connections that never opted into compression risks misinterpreting an
+/// arbitrary `Bytes` payload that happens to start with it.
+fn inflate_value(value: Value) -> Value {
+    match value {
+        Value::BulkString(bytes) => Value::BulkString(decompress_frame(&bytes)),
+        Value::Array(items) => Value::Array(items.into_iter().map(inflate_value).collect()),
+        Value::Set(items) => Value::Set(items.into_iter().map(inflate_value).collect()),
+        Value::Map(items) => Value::Map(
+            items
+                .into_iter()
+                .map(|(k, v)| (inflate_value(k), inflate_value(v)))
+                .collect(),
+        ),
+        Value::Push { kind, data } => Value::Push {
+            kind,
+            data: data.into_iter().map(inflate_value).collect(),
+        },
+        Value::Attribute { data, attributes } => Value::Attribute {
+            data: Box::new(inflate_value(*data)),
+            attributes,
+        },
+        other => other,
+    }
+}
+
+/// Inflates `value` via [`inflate_value`] only when `config.mode` has compression enabled;
+/// otherwise returns `value` untouched. Call sites should use this instead of `inflate_value`
+/// directly so connections that never opted into compression never run the magic-byte probe.
+pub(crate) fn inflate_if_enabled(config: &CompressionConfig, value: Value) -> Value {
+    match config.mode {
+        CompressionMode::None => value,
+        CompressionMode::Lz4 | CompressionMode::Snappy => inflate_value(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_large_value_through_lz4() {
+        let config = CompressionConfig {
+            mode: CompressionMode::Lz4,
+            threshold: 8,
+        };
+        let original = b"hello world, this payload is long enough to pass the threshold".to_vec();
+        let frame = config.compress(&original).expect("value exceeds threshold");
+        assert_eq!(decompress_frame(&frame), original);
+    }
+
+    #[test]
+    fn round_trips_a_large_value_through_snappy() {
+        let config = CompressionConfig {
+            mode: CompressionMode::Snappy,
+            threshold: 8,
+        };
+        let original = b"hello world, this payload is long enough to pass the threshold".to_vec();
+        let frame = config.compress(&original).expect("value exceeds threshold");
+        assert_eq!(decompress_frame(&frame), original);
+    }
+
+    #[test]
+    fn leaves_values_below_threshold_untouched() {
+        let config = CompressionConfig {
+            mode: CompressionMode::Lz4,
+            threshold: 1024,
+        };
+        assert_eq!(config.compress(b"short"), None);
+    }
+
+    #[test]
+    fn leaves_unframed_values_untouched_on_read() {
+        let plain = b"written by another client".to_vec();
+        assert_eq!(decompress_frame(&plain), plain);
+    }
+
+    #[test]
+    fn inflate_if_enabled_skips_the_magic_byte_probe_when_mode_is_none() {
+        let config = CompressionConfig {
+            mode: CompressionMode::None,
+            threshold: 1024,
+        };
+        // Looks like a compression frame (starts with MAGIC_BYTE) but is actually a legitimate
+        // payload from a Bytes parameter; with compression disabled it must pass through as-is.
+        let looks_framed = vec![MAGIC_BYTE, LZ4_ALGORITHM_ID, 0, 0, 0, 3, 9, 9, 9];
+        let value = Value::BulkString(looks_framed.clone());
+        assert_eq!(
+            inflate_if_enabled(&config, value),
+            Value::BulkString(looks_framed)
+        );
+    }
+
+    #[test]
+    fn inflate_if_enabled_inflates_when_mode_is_enabled() {
+        let config = CompressionConfig {
+            mode: CompressionMode::Lz4,
+            threshold: 8,
+        };
+        let original = b"hello world, this payload is long enough to pass the threshold".to_vec();
+        let frame = config.compress(&original).expect("value exceeds threshold");
+        assert_eq!(
+            inflate_if_enabled(&config, Value::BulkString(frame)),
+            Value::BulkString(original)
+        );
+    }
+}
@@ -4,16 +4,20 @@ extern crate core;
 
 mod apihandle;
 mod buffering;
+mod capabilities;
+mod compression;
 mod conreq;
 mod data;
 mod helpers;
 mod logging;
 mod parameter;
 mod routing;
+mod subscribe;
 mod value;
 
-use crate::apihandle::{CommandParameter, Handle};
+use crate::apihandle::{CommandParameter, Handle, PerCallPolicy, RetryPolicy};
 use crate::buffering::FFIBuffer;
+use crate::compression::{CompressionConfig, CompressionMode};
 use crate::conreq::ConnectionRequest;
 use crate::data::*;
 use crate::parameter::Parameter;
@@ -21,7 +25,7 @@ use crate::value::Value;
 use glide_core::client::ConnectionError;
 use glide_core::request_type::RequestType;
 use logger_core::{LazyRollingFileAppender, Reloads, INITIATE_ONCE};
-use std::ffi::{c_int, c_void, CString};
+use std::ffi::{c_int, c_uchar, c_void, CString};
 use std::os::raw::c_char;
 use std::panic::catch_unwind;
 use std::ptr::null;
@@ -109,6 +113,111 @@ pub extern "C-unwind" fn csharp_set_logging_hooks(
     };
 }
 
+/// # Summary
+/// Configures [`Handle::command`]'s retry-with-backoff behavior for transient failures.
+///
+/// `max_attempts` counts the initial try (`1` disables retrying). Delays between attempts
+/// grow exponentially from `base_delay_ms`, capped at `max_delay_ms`, with jitter applied.
+#[repr(C)]
+pub struct RetryPolicyConfig {
+    pub max_attempts: c_int,
+    pub base_delay_ms: c_int,
+    pub max_delay_ms: c_int,
+}
+
+impl From<RetryPolicyConfig> for RetryPolicy {
+    fn from(value: RetryPolicyConfig) -> Self {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: if value.max_attempts > 0 {
+                value.max_attempts as u32
+            } else {
+                default.max_attempts
+            },
+            base_delay: if value.base_delay_ms > 0 {
+                std::time::Duration::from_millis(value.base_delay_ms as u64)
+            } else {
+                default.base_delay
+            },
+            max_delay: if value.max_delay_ms > 0 {
+                std::time::Duration::from_millis(value.max_delay_ms as u64)
+            } else {
+                default.max_delay
+            },
+        }
+    }
+}
+
+/// # Summary
+/// Per-call override of `csharp_command`'s retry/timeout behavior, modeled as a CI-style
+/// `retries = N` count plus a `slow-timeout` period with a `terminate-after` overrun count.
+///
+/// `slow_timeout_ms` of `0` disables the per-attempt deadline (attempts only bounded by the
+/// underlying connection). `terminate_after` of `0` is treated as `1`: the first overrun
+/// terminates the in-flight attempt.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PerCallPolicyConfig {
+    pub retries: c_int,
+    pub slow_timeout_ms: c_int,
+    pub terminate_after: c_int,
+}
+
+impl From<PerCallPolicyConfig> for PerCallPolicy {
+    fn from(value: PerCallPolicyConfig) -> Self {
+        PerCallPolicy {
+            retries: value.retries.max(0) as u32,
+            slow_timeout: if value.slow_timeout_ms > 0 {
+                Some(std::time::Duration::from_millis(
+                    value.slow_timeout_ms as u64,
+                ))
+            } else {
+                None
+            },
+            terminate_after: value.terminate_after.max(1) as u32,
+        }
+    }
+}
+
+/// # Summary
+/// Selects the opt-in client-side compression algorithm for large bulk values.
+#[repr(C)]
+pub enum ECompressionMode {
+    None,
+    Lz4,
+    Snappy,
+}
+
+/// # Summary
+/// Configures opt-in compression of large `String`/`Bytes` parameters.
+///
+/// Only payloads longer than `threshold_bytes` (before framing) are compressed; values of at
+/// most that size are always written as plain bulk strings, and values written by a non-compressing
+/// client are always read back untouched regardless of this setting.
+#[repr(C)]
+pub struct CompressionConfigFfi {
+    pub mode: ECompressionMode,
+    pub threshold_bytes: c_int,
+}
+
+impl From<CompressionConfigFfi> for CompressionConfig {
+    fn from(value: CompressionConfigFfi) -> Self {
+        let default = CompressionConfig::default();
+        CompressionConfig {
+            mode: match value.mode {
+                ECompressionMode::None => CompressionMode::None,
+                ECompressionMode::Lz4 => CompressionMode::Lz4,
+                ECompressionMode::Snappy => CompressionMode::Snappy,
+            },
+            threshold: if value.threshold_bytes > 0 {
+                value.threshold_bytes as usize
+            } else {
+                default.threshold
+            },
+        }
+    }
+}
+
 /// # Summary
 /// Creates a new client to the given address.
 ///
@@ -129,6 +238,8 @@ pub extern "C-unwind" fn csharp_set_logging_hooks(
 #[no_mangle]
 pub extern "C-unwind" fn csharp_create_client_handle(
     in_connection_request: ConnectionRequest,
+    in_retry_policy: RetryPolicyConfig,
+    in_compression: CompressionConfigFfi,
 ) -> CreateClientHandleResult {
     let request = match in_connection_request.to_redis() {
         Ok(d) => d,
@@ -176,7 +287,11 @@ pub extern "C-unwind" fn csharp_create_client_handle(
     let handle: Handle;
     {
         let _runtime_handle = runtime.enter();
-        handle = match runtime.block_on(Handle::create(request)) {
+        handle = match runtime.block_on(Handle::create(
+            request,
+            in_retry_policy.into(),
+            in_compression.into(),
+        )) {
             Ok(d) => d,
             Err(e) => {
                 let str = e.to_string();
@@ -203,6 +318,20 @@ pub extern "C-unwind" fn csharp_create_client_handle(
             }
         };
     }
+    {
+        let _runtime_handle = runtime.enter();
+        // Best-effort: if capability detection fails (e.g. the server doesn't support `HELLO`),
+        // `csharp_get_server_capabilities` simply reports nothing cached rather than failing
+        // connection setup over a non-essential probe.
+        if let Err(e) =
+            runtime.block_on(capabilities::detect_and_cache(handle.identity_key(), &handle))
+        {
+            logger_core::log_error(
+                "csharp_ffi",
+                format!("Failed to detect server capabilities: {:?}", e),
+            );
+        }
+    }
     CreateClientHandleResult {
         result: ECreateClientHandleCode::Success,
         client_handle: Box::into_raw(Box::new(FFIHandle { runtime, handle })) as *const c_void,
@@ -231,11 +360,242 @@ pub extern "C-unwind" fn csharp_create_client_handle(
 pub extern "C-unwind" fn csharp_free_client_handle(in_client_ptr: *const c_void) {
     logger_core::log_trace("csharp_ffi", "Entered csharp_free_client_handle");
     let client_ptr = unsafe { Box::from_raw(in_client_ptr as *mut FFIHandle) };
+    let identity_key = client_ptr.handle.identity_key();
     let _runtime_handle = client_ptr.runtime.enter();
     drop(client_ptr);
+    // Must happen after the identity key is read above but can run after the handle itself is
+    // dropped: both maps are keyed by `identity_key`, not by the dropped `Handle` value.
+    subscribe::forget(identity_key);
+    capabilities::forget(identity_key);
     logger_core::log_trace("csharp_ffi", "Exiting csharp_free_client_handle");
 }
 
+/// # Summary
+/// Which server implementation answered the capability probe run at `csharp_create_client_handle`
+/// time. `Unknown` means detection hasn't completed, failed, or the server's `INFO` reply didn't
+/// contain a recognizable version line.
+#[repr(C)]
+pub enum EEngine {
+    Unknown,
+    Redis,
+    Valkey,
+}
+
+impl From<capabilities::Engine> for EEngine {
+    fn from(value: capabilities::Engine) -> Self {
+        match value {
+            capabilities::Engine::Unknown => EEngine::Unknown,
+            capabilities::Engine::Redis => EEngine::Redis,
+            capabilities::Engine::Valkey => EEngine::Valkey,
+        }
+    }
+}
+
+/// # Summary
+/// Result of `csharp_get_server_capabilities`. `success` is false until the one-time capability
+/// probe run at connect time has completed (or if it failed), in which case every other field is
+/// a default/zero value.
+///
+/// # Remarks
+/// `feature_flags` bit `0` is pub-sub sharding (`SSUBSCRIBE`), bit `1` is functions, and bit `2`
+/// is client-side caching (tracking) — all inferred from `version_major`/`minor`/`patch`.
+#[repr(C)]
+pub struct ServerCapabilitiesResult {
+    pub success: c_int,
+    pub engine: EEngine,
+    pub version_major: c_int,
+    pub version_minor: c_int,
+    pub version_patch: c_int,
+    pub resp_protocol: c_int,
+    pub feature_flags: u32,
+}
+
+/// # Summary
+/// Returns the server capabilities detected once at connect time (engine, version, negotiated
+/// RESP protocol level, and a `feature_flags` bitset — see `ServerCapabilitiesResult`), so the caller can
+/// feature-gate commands instead of discovering a missing one at runtime.
+///
+/// # Remarks
+/// This is a cheap accessor over a cache populated by `csharp_create_client_handle`; it never
+/// issues a command. `success` is false if that initial detection failed or hasn't run.
+#[no_mangle]
+pub extern "C-unwind" fn csharp_get_server_capabilities(
+    in_client_ptr: *const c_void,
+) -> ServerCapabilitiesResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_get_server_capabilities");
+    if in_client_ptr.is_null() {
+        logger_core::log_error(
+            "csharp_ffi",
+            "Error in csharp_get_server_capabilities called with null handle",
+        );
+        return ServerCapabilitiesResult {
+            success: false as c_int,
+            engine: EEngine::Unknown,
+            version_major: 0,
+            version_minor: 0,
+            version_patch: 0,
+            resp_protocol: 0,
+            feature_flags: 0,
+        };
+    }
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let result = match capabilities::cached(ffi_handle.handle.identity_key()) {
+        Some(caps) => ServerCapabilitiesResult {
+            success: true as c_int,
+            engine: caps.engine.into(),
+            version_major: caps.version.0 as c_int,
+            version_minor: caps.version.1 as c_int,
+            version_patch: caps.version.2 as c_int,
+            resp_protocol: caps.resp_protocol as c_int,
+            feature_flags: caps.features.as_bits(),
+        },
+        None => ServerCapabilitiesResult {
+            success: false as c_int,
+            engine: EEngine::Unknown,
+            version_major: 0,
+            version_minor: 0,
+            version_patch: 0,
+            resp_protocol: 0,
+            feature_flags: 0,
+        },
+    };
+    logger_core::log_trace("csharp_ffi", "Exiting csharp_get_server_capabilities");
+    result
+}
+
+/// # Summary
+/// Result of `csharp_subscribe`: either a subscription handle to pass to `csharp_unsubscribe`,
+/// or an error describing why the subscription could not be created.
+#[repr(C)]
+pub struct SubscribeResult {
+    pub success: c_int,
+    pub subscription_id: u64,
+    pub error_string: *const c_char,
+}
+
+/// # Summary
+/// Subscribes to a set of pub-sub channels, re-invoking *in_callback* for every message
+/// delivered until *csharp_unsubscribe* is called for the returned handle. Issues the matching
+/// `SUBSCRIBE`/`PSUBSCRIBE`/`SSUBSCRIBE` to the server before returning.
+///
+/// # Params
+/// ***in_client_ptr*** An active client handle
+/// ***in_callback*** A persistent callback with the signature:
+///                   `void Callback(void * in_data, ESubscriptionKind kind, const char * channel, int channel_len, const unsigned char * payload, int payload_len)`.
+///                   It is re-invoked for every push message on a subscribed channel.
+/// ***in_callback_data*** The data to be passed in to *in_callback*; must outlive the subscription.
+/// ***in_kind*** Whether `in_channels` are plain channels, glob patterns, or shard channels —
+///               selects `SUBSCRIBE`, `PSUBSCRIBE`, or `SSUBSCRIBE` respectively.
+/// ***in_channels*** An array of channel/pattern names to subscribe to, with the size of `in_channels_count`.
+/// ***in_channels_count*** The number of entries in *in_channels*.
+///
+/// # Input Safety (in_...)
+/// The data passed in is considered "caller responsibility".
+/// Any pointers hence will be left unreleased after leaving.
+///
+/// # Output Safety (out_... / return ...)
+/// The returned subscription handle must be passed to `csharp_unsubscribe` to stop delivery.
+#[no_mangle]
+pub extern "C-unwind" fn csharp_subscribe(
+    in_client_ptr: *const c_void,
+    in_callback: subscribe::SubscriptionCallback,
+    in_callback_data: *mut c_void,
+    in_kind: subscribe::ESubscribeKind,
+    in_channels: *const *const c_char,
+    in_channels_count: c_int,
+) -> SubscribeResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_subscribe");
+    if in_client_ptr.is_null() {
+        logger_core::log_error(
+            "csharp_ffi",
+            "Error in csharp_subscribe called with null handle",
+        );
+        return SubscribeResult {
+            success: false as c_int,
+            subscription_id: 0,
+            error_string: helpers::to_cstr_ptr_or_null("Null handle passed"),
+        };
+    }
+    let channels = match helpers::grab_vec(in_channels, in_channels_count as usize, |ptr| {
+        helpers::grab_str_not_null(*ptr)
+    }) {
+        Ok(d) => d,
+        Err(e) => {
+            logger_core::log_error(
+                "csharp_ffi",
+                format!("Error in channel name transformation: {:?}", e.to_string()),
+            );
+            return SubscribeResult {
+                success: false as c_int,
+                subscription_id: 0,
+                error_string: helpers::to_cstr_ptr_or_null(e.to_string().as_str()),
+            };
+        }
+    };
+
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let handle = &ffi_handle.handle;
+    let _runtime_handle = ffi_handle.runtime.enter();
+    let subscription_id = match ffi_handle.runtime.block_on(subscribe::subscribe(
+        handle.identity_key(),
+        handle,
+        in_kind,
+        channels,
+        in_callback,
+        in_callback_data,
+    )) {
+        Ok(d) => d,
+        Err(e) => {
+            logger_core::log_error(
+                "csharp_ffi",
+                format!("Error sending subscribe command: {:?}", e.to_string()),
+            );
+            return SubscribeResult {
+                success: false as c_int,
+                subscription_id: 0,
+                error_string: helpers::to_cstr_ptr_or_null(e.to_string().as_str()),
+            };
+        }
+    };
+
+    logger_core::log_trace("csharp_ffi", "Exiting csharp_subscribe");
+    SubscribeResult {
+        success: true as c_int,
+        subscription_id,
+        error_string: null(),
+    }
+}
+
+/// # Summary
+/// Stops delivery for a subscription previously returned by `csharp_subscribe`, issuing the
+/// matching `UNSUBSCRIBE`/`PUNSUBSCRIBE`/`SUNSUBSCRIBE` for any channel/pattern that no longer
+/// has a local subscriber as a result.
+#[no_mangle]
+pub extern "C-unwind" fn csharp_unsubscribe(in_client_ptr: *const c_void, in_subscription_id: u64) {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_unsubscribe");
+    if in_client_ptr.is_null() {
+        logger_core::log_error(
+            "csharp_ffi",
+            "Error in csharp_unsubscribe called with null handle",
+        );
+        return;
+    }
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let handle = &ffi_handle.handle;
+    let _runtime_handle = ffi_handle.runtime.enter();
+    if let Err(e) = ffi_handle.runtime.block_on(subscribe::unsubscribe(
+        handle.identity_key(),
+        handle,
+        in_subscription_id,
+    )) {
+        logger_core::log_error(
+            "csharp_ffi",
+            format!("Error sending unsubscribe command: {:?}", e.to_string()),
+        );
+    }
+    logger_core::log_trace("csharp_ffi", "Exiting csharp_unsubscribe");
+}
+
 /// # Summary
 /// Method to invoke a command.
 ///
@@ -266,6 +626,12 @@ pub extern "C-unwind" fn csharp_free_client_handle(in_client_ptr: *const c_void)
 /// # Freeing data allocated by the API
 /// To free data returned by the API, use the corresponding `free_...` methods of the API.
 /// It is **not optional** to call them to free data allocated by the API!
+///
+/// ***in_call_policy*** Either nullptr to use the handle-wide retry policy set at
+///                      `csharp_create_client_handle` time, or a per-call override: `retries`
+///                      attempts, a `slow_timeout_ms` deadline per attempt (`0` disables the
+///                      deadline), and `terminate_after` consecutive overruns before the
+///                      in-flight attempt is terminated and a timeout is reported.
 #[no_mangle]
 pub extern "C-unwind" fn csharp_command(
     in_client_ptr: *const c_void,
@@ -277,6 +643,7 @@ pub extern "C-unwind" fn csharp_command(
     //       handling the different input types.
     in_args: *const Parameter,
     in_args_count: c_int,
+    in_call_policy: *const PerCallPolicyConfig,
     // ToDo: Pass in ActivityContext and connect C# OTEL with Rust OTEL
 ) -> CommandResult {
     logger_core::log_trace("csharp_ffi", "Entered csharp_command");
@@ -318,6 +685,11 @@ pub extern "C-unwind" fn csharp_command(
     };
     let callback = in_callback;
     let callback_data = in_callback_data as usize;
+    let call_policy = if in_call_policy.is_null() {
+        None
+    } else {
+        Some(unsafe { (*in_call_policy).into() })
+    };
 
     let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
     let handle = ffi_handle.handle.clone();
@@ -352,7 +724,10 @@ pub extern "C-unwind" fn csharp_command(
     ffi_handle.runtime.spawn(async move {
         let args = args;
         logger_core::log_trace("csharp_ffi", "Entered command task with");
-        let data: redis::Value = match handle.command(cmd, args.as_slice(), routing_info).await {
+        let data: redis::Value = match handle
+            .command_with_policy(cmd, args.as_slice(), routing_info, call_policy)
+            .await
+        {
             Ok(d) => d,
             Err(e) => {
                 logger_core::log_error(
@@ -450,6 +825,446 @@ pub extern "C-unwind" fn csharp_command(
     CommandResult::new_success()
 }
 
+/// # Summary
+/// Describes a single command as part of a `csharp_pipeline` batch.
+#[repr(C)]
+pub struct PipelineCommand {
+    pub request_type: RequestType,
+    pub args: *const Parameter,
+    pub args_count: c_int,
+}
+
+/// # Summary
+/// Method to invoke a batch of commands in a single round trip.
+///
+/// # Params
+/// ***in_client_ptr*** An active client handle
+/// ***in_callback*** A callback method with the signature:
+///                   `void Callback(void * in_data, int out_success, const Value value)`.
+///                   The first arg contains the data of the parameter *in_callback_data*;
+///                   the second arg indicates whether the third parameter contains the error or result;
+///                   the third arg contains either the result (as an array Value aligned with
+///                   *in_commands*) and MUST be freed by the callback.
+/// ***in_callback_data*** The data to be passed in to *in_callback*.
+/// ***in_atomic*** When true, the batch is wrapped in MULTI/EXEC as a transaction; otherwise it
+///                 is issued as a plain pipeline.
+/// ***in_routing_info*** Either nullptr or the routing info to use for the whole batch.
+/// ***in_commands*** An array of commands to be issued, with the size of `in_commands_count`.
+/// ***in_commands_count*** The number of commands in *in_commands*.
+///
+/// # Input Safety (in_...)
+/// The data passed in is considered "caller responsibility".
+/// Any pointers hence will be left unreleased after leaving.
+///
+/// # Output Safety (out_... / return ...)
+/// The data returned is considered "caller responsibility".
+/// The caller must release any non-null pointers.
+///
+/// # Reference Safety (ref_...)
+/// Any reference data is considered "caller owned".
+///
+/// # Freeing data allocated by the API
+/// To free data returned by the API, use the corresponding `free_...` methods of the API.
+/// It is **not optional** to call them to free data allocated by the API!
+#[no_mangle]
+pub extern "C-unwind" fn csharp_pipeline(
+    in_client_ptr: *const c_void,
+    in_callback: CommandCallback,
+    in_callback_data: *mut c_void,
+    in_atomic: c_int,
+    in_routing_info: *const routing::RoutingInfo,
+    in_commands: *const PipelineCommand,
+    in_commands_count: c_int,
+) -> CommandResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_pipeline");
+    if in_client_ptr.is_null() {
+        logger_core::log_error(
+            "csharp_ffi",
+            "Error in csharp_pipeline called with null handle",
+        );
+        return CommandResult::new_error(helpers::to_cstr_ptr_or_null("Null handle passed"));
+    }
+    let commands = match helpers::grab_vec(
+        in_commands,
+        in_commands_count as usize,
+        |entry: &PipelineCommand| {
+            let args = helpers::grab_vec(entry.args, entry.args_count as usize, |el| {
+                Ok::<CommandParameter, Utf8OrEmptyError>(unsafe { el.to_command_parameter() }?)
+            })?;
+            let cmd = match entry.request_type.get_command() {
+                None => return Err(Utf8OrEmptyError::Empty),
+                Some(d) => d,
+            };
+            Ok::<(redis::Cmd, Vec<CommandParameter>), Utf8OrEmptyError>((cmd, args))
+        },
+    ) {
+        Ok(d) => d,
+        Err(e) => {
+            logger_core::log_error(
+                "csharp_ffi",
+                format!("Error in pipeline command transformation: {:?}", e.to_string()),
+            );
+            return match e {
+                Utf8OrEmptyError::Utf8Error(e) => {
+                    CommandResult::new_error(helpers::to_cstr_ptr_or_null(e.to_string().as_str()))
+                }
+                Utf8OrEmptyError::Empty => CommandResult::new_error(helpers::to_cstr_ptr_or_null(
+                    "Null value passed, or unknown request type in pipeline",
+                )),
+            };
+        }
+    };
+    let atomic = in_atomic != 0;
+    let callback = in_callback;
+    let callback_data = in_callback_data as usize;
+
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let handle = ffi_handle.handle.clone();
+    let routing_info = if in_routing_info.is_null() {
+        None
+    } else {
+        Some(unsafe {
+            match (*in_routing_info).to_redis() {
+                Ok(d) => d,
+                Err(e) => {
+                    logger_core::log_error(
+                        "csharp_ffi",
+                        format!(
+                            "Error while parsing route in string transformation: {:?}",
+                            e.to_string()
+                        ),
+                    );
+                    return match e {
+                        Utf8OrEmptyError::Utf8Error(e) => CommandResult::new_error(
+                            helpers::to_cstr_ptr_or_null(e.to_string().as_str()),
+                        ),
+                        Utf8OrEmptyError::Empty => {
+                            CommandResult::new_error(helpers::to_cstr_ptr_or_null(
+                                "Routing info incomplete, null value passed in string",
+                            ))
+                        }
+                    };
+                }
+            }
+        })
+    };
+    ffi_handle.runtime.spawn(async move {
+        let commands = commands;
+        logger_core::log_trace("csharp_ffi", "Entered pipeline task with");
+        let data = match handle.pipeline(commands, atomic, routing_info).await {
+            Ok(d) => redis::Value::Array(d),
+            Err(e) => {
+                logger_core::log_error(
+                    "csharp_ffi",
+                    format!(
+                        "Error handling command in task of csharp_pipeline: {:?}",
+                        e.to_string()
+                    ),
+                );
+                let value = Value::simple_string_with_null(e.to_string().as_str());
+                if let Err(e) = catch_unwind(|| unsafe {
+                    callback(callback_data as *mut c_void, false as c_int, value);
+                }) {
+                    logger_core::log_error(
+                        "csharp_ffi",
+                        format!("Exception in C# callback: {:?}", e),
+                    );
+                }
+                return;
+            }
+        };
+        unsafe {
+            let mut buffer = FFIBuffer::new();
+
+            // "Simulation" run
+            _ = Value::from_redis(&data, &mut buffer);
+            buffer.switch_mode();
+
+            match Value::from_redis(&data, &mut buffer) {
+                Ok(data) => {
+                    if let Err(e) = catch_unwind(|| {
+                        callback(callback_data as *mut c_void, true as c_int, data);
+                    }) {
+                        logger_core::log_error(
+                            "csharp_ffi",
+                            format!("Exception in C# callback: {:?}", e),
+                        );
+                    }
+                }
+                Err(e) => {
+                    logger_core::log_error(
+                        "csharp_ffi",
+                        format!(
+                            "Error transforming command result in task of csharp_pipeline: {:?}",
+                            e.to_string()
+                        ),
+                    );
+                    if let Err(e) = catch_unwind(|| {
+                        callback(
+                            callback_data as *mut c_void,
+                            false as c_int,
+                            Value::simple_string_with_null(e.to_string().as_str()),
+                        );
+                    }) {
+                        logger_core::log_error(
+                            "csharp_ffi",
+                            format!("Exception in C# callback: {:?}", e),
+                        );
+                    }
+                }
+            }
+        }
+
+        logger_core::log_trace("csharp_ffi", "Exiting tokio spawn from csharp_pipeline");
+    });
+
+    logger_core::log_trace("csharp_ffi", "Exiting csharp_pipeline");
+    CommandResult::new_success()
+}
+
+/// # Summary
+/// Result of `csharp_load_script`: either the hex SHA1 to pass to `csharp_invoke_script_sha`, or
+/// an error. `sha1` MUST be freed with `csharp_free_string`.
+#[repr(C)]
+pub struct LoadScriptResult {
+    pub success: c_int,
+    pub sha1: *const c_char,
+    pub error_string: *const c_char,
+}
+
+/// # Summary
+/// Caches a Lua script body under its SHA1 so it can later be invoked with
+/// `csharp_invoke_script_sha`, mirroring Redis/Valkey's `SCRIPT LOAD`.
+#[no_mangle]
+pub extern "C-unwind" fn csharp_load_script(
+    in_client_ptr: *const c_void,
+    in_script: *const c_uchar,
+    in_script_len: c_int,
+) -> LoadScriptResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_load_script");
+    if in_client_ptr.is_null() {
+        return LoadScriptResult {
+            success: false as c_int,
+            sha1: null(),
+            error_string: helpers::to_cstr_ptr_or_null("Null handle passed"),
+        };
+    }
+    let script = helpers::grab_vec(in_script, in_script_len as usize, |byte| {
+        Ok::<u8, ()>(*byte)
+    })
+    .unwrap(); // Safe because the grab func will never return non-ok values
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let sha = ffi_handle.handle.load_script(&script);
+    logger_core::log_trace("csharp_ffi", "Exiting csharp_load_script");
+    LoadScriptResult {
+        success: true as c_int,
+        sha1: match CString::from_str(sha.as_str()) {
+            Ok(d) => d.into_raw(),
+            Err(_) => null(),
+        },
+        error_string: null(),
+    }
+}
+
+/// # Summary
+/// Evaluates a Lua script body: caches it under its SHA1, then sends `EVALSHA`, falling back to
+/// `EVAL` with the body on a `NOSCRIPT` error. Uses the same async-callback contract as
+/// `csharp_command`.
+///
+/// # Params
+/// ***in_script*** The script body, with the size of `in_script_len`.
+/// ***in_keys*** The `KEYS` array passed to the script, with the size of `in_keys_count`.
+/// ***in_args*** The `ARGV` array passed to the script, with the size of `in_args_count`.
+/// ***in_routing_info*** Either nullptr or the routing info to use for the command (important on
+///                       clusters so the script runs on the node owning the keys).
+#[no_mangle]
+pub extern "C-unwind" fn csharp_eval_script(
+    in_client_ptr: *const c_void,
+    in_callback: CommandCallback,
+    in_callback_data: *mut c_void,
+    in_script: *const c_uchar,
+    in_script_len: c_int,
+    in_keys: *const Parameter,
+    in_keys_count: c_int,
+    in_args: *const Parameter,
+    in_args_count: c_int,
+    in_routing_info: *const routing::RoutingInfo,
+) -> CommandResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_eval_script");
+    if in_client_ptr.is_null() {
+        return CommandResult::new_error(helpers::to_cstr_ptr_or_null("Null handle passed"));
+    }
+    let script = helpers::grab_vec(in_script, in_script_len as usize, |byte| {
+        Ok::<u8, ()>(*byte)
+    })
+    .unwrap(); // Safe because the grab func will never return non-ok values
+    run_script_task(
+        in_client_ptr,
+        in_callback,
+        in_callback_data,
+        in_keys,
+        in_keys_count,
+        in_args,
+        in_args_count,
+        in_routing_info,
+        move |handle, keys, args, routing| {
+            let script = script.clone();
+            async move { handle.eval_script(&script, keys, args, routing).await }
+        },
+    )
+}
+
+/// # Summary
+/// Invokes a previously cached script by its SHA1 via `EVALSHA`, transparently falling back to
+/// `EVAL` with the cached body (and repopulating the node) on a `NOSCRIPT` error. Uses the same
+/// async-callback contract as `csharp_command`.
+#[no_mangle]
+pub extern "C-unwind" fn csharp_invoke_script_sha(
+    in_client_ptr: *const c_void,
+    in_callback: CommandCallback,
+    in_callback_data: *mut c_void,
+    in_sha1: *const c_char,
+    in_keys: *const Parameter,
+    in_keys_count: c_int,
+    in_args: *const Parameter,
+    in_args_count: c_int,
+    in_routing_info: *const routing::RoutingInfo,
+) -> CommandResult {
+    logger_core::log_trace("csharp_ffi", "Entered csharp_invoke_script_sha");
+    if in_client_ptr.is_null() {
+        return CommandResult::new_error(helpers::to_cstr_ptr_or_null("Null handle passed"));
+    }
+    let sha = match helpers::grab_str_not_null(in_sha1) {
+        Ok(d) => d,
+        Err(_) => {
+            return CommandResult::new_error(helpers::to_cstr_ptr_or_null(
+                "Null value passed for sha1",
+            ))
+        }
+    };
+    run_script_task(
+        in_client_ptr,
+        in_callback,
+        in_callback_data,
+        in_keys,
+        in_keys_count,
+        in_args,
+        in_args_count,
+        in_routing_info,
+        move |handle, keys, args, routing| {
+            let sha = sha.clone();
+            async move { handle.invoke_script(&sha, keys, args, routing).await }
+        },
+    )
+}
+
+/// Shared plumbing for `csharp_eval_script`/`csharp_invoke_script_sha`: parses `KEYS`/`ARGV`,
+/// resolves routing, and spawns a task that runs `invoke` and reports through the same
+/// `CommandCallback` contract as `csharp_command`.
+fn run_script_task<F, Fut>(
+    in_client_ptr: *const c_void,
+    in_callback: CommandCallback,
+    in_callback_data: *mut c_void,
+    in_keys: *const Parameter,
+    in_keys_count: c_int,
+    in_args: *const Parameter,
+    in_args_count: c_int,
+    in_routing_info: *const routing::RoutingInfo,
+    invoke: F,
+) -> CommandResult
+where
+    F: FnOnce(Handle, Vec<CommandParameter>, Vec<CommandParameter>, Option<redis::cluster_routing::RoutingInfo>) -> Fut
+        + Send
+        + 'static,
+    Fut: std::future::Future<Output = Result<redis::Value, redis::RedisError>> + Send,
+{
+    let to_params = |ptr: *const Parameter, count: c_int| {
+        helpers::grab_vec(ptr, count as usize, |el| {
+            Ok::<CommandParameter, Utf8OrEmptyError>(unsafe { el.to_command_parameter() }?)
+        })
+    };
+    let keys = match to_params(in_keys, in_keys_count) {
+        Ok(d) => d,
+        Err(e) => {
+            return CommandResult::new_error(helpers::to_cstr_ptr_or_null(e.to_string().as_str()))
+        }
+    };
+    let args = match to_params(in_args, in_args_count) {
+        Ok(d) => d,
+        Err(e) => {
+            return CommandResult::new_error(helpers::to_cstr_ptr_or_null(e.to_string().as_str()))
+        }
+    };
+
+    let ffi_handle = unsafe { Box::leak(Box::from_raw(in_client_ptr as *mut FFIHandle)) };
+    let handle = ffi_handle.handle.clone();
+    let routing_info = if in_routing_info.is_null() {
+        None
+    } else {
+        match unsafe { (*in_routing_info).to_redis() } {
+            Ok(d) => Some(d),
+            Err(e) => {
+                return CommandResult::new_error(helpers::to_cstr_ptr_or_null(
+                    e.to_string().as_str(),
+                ))
+            }
+        }
+    };
+
+    let callback = in_callback;
+    let callback_data = in_callback_data as usize;
+    ffi_handle.runtime.spawn(async move {
+        let data = match invoke(handle, keys, args, routing_info).await {
+            Ok(d) => d,
+            Err(e) => {
+                let value = Value::simple_string_with_null(e.to_string().as_str());
+                if let Err(e) = catch_unwind(|| unsafe {
+                    callback(callback_data as *mut c_void, false as c_int, value);
+                }) {
+                    logger_core::log_error(
+                        "csharp_ffi",
+                        format!("Exception in C# callback: {:?}", e),
+                    );
+                }
+                return;
+            }
+        };
+        unsafe {
+            let mut buffer = FFIBuffer::new();
+            _ = Value::from_redis(&data, &mut buffer);
+            buffer.switch_mode();
+            match Value::from_redis(&data, &mut buffer) {
+                Ok(data) => {
+                    if let Err(e) = catch_unwind(|| {
+                        callback(callback_data as *mut c_void, true as c_int, data);
+                    }) {
+                        logger_core::log_error(
+                            "csharp_ffi",
+                            format!("Exception in C# callback: {:?}", e),
+                        );
+                    }
+                }
+                Err(e) => {
+                    if let Err(e) = catch_unwind(|| {
+                        callback(
+                            callback_data as *mut c_void,
+                            false as c_int,
+                            Value::simple_string_with_null(e.to_string().as_str()),
+                        );
+                    }) {
+                        logger_core::log_error(
+                            "csharp_ffi",
+                            format!("Exception in C# callback: {:?}", e),
+                        );
+                    }
+                }
+            }
+        }
+    });
+
+    CommandResult::new_success()
+}
+
 #[cfg(test)]
 mod tests {
     #[allow(dead_code)]
@@ -37,6 +37,7 @@ pub enum EParameterKind {
     Float32Array,
     Float64Array,
     KeyValueArray,
+    Bytes,
 }
 
 #[repr(C)]
@@ -53,6 +54,7 @@ pub union ParameterValue {
     pub f32: c_float,
     pub f64: c_double,
     pub string: *const c_char,
+    pub bytes: *const c_uchar,
     pub flag_array: *const c_char,
     pub i8_array: *const c_char,
     pub u8_array: *const c_uchar,
@@ -92,6 +94,14 @@ impl Parameter {
                 let str = helpers::grab_str_not_null(self.value.string)?;
                 CommandParameter::String(str)
             }
+            EParameterKind::Bytes => {
+                let bytes =
+                    helpers::grab_vec(self.value.bytes, self.value_length as usize, |byte| {
+                        Ok::<u8, ()>(*byte)
+                    })
+                    .unwrap(); // Safe because the grab func will never return non-ok values
+                CommandParameter::Bytes(bytes)
+            }
             EParameterKind::BoolArray => {
                 let arr =
                     helpers::grab_vec(self.value.flag_array, self.value_length as usize, |flag| {
@@ -194,4 +204,65 @@ impl Parameter {
             }
         })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::ToRedisArgs;
+    use std::ffi::CString;
+
+    fn flatten(param: &CommandParameter) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        param.write_redis_args(&mut out);
+        out
+    }
+
+    // Drives the real unsafe FFI entry point (`Parameter::to_command_parameter`) over a
+    // `KeyParameterPair` array the way a C# caller's marshalled array actually would, rather than
+    // constructing a `CommandParameter::KeyValueArray` directly in Rust. `KeyParameterPair` is
+    // larger than one byte, so a `grab_vec` that stepped by anything other than one element per
+    // pair would read the wrong pairs here.
+    #[test]
+    fn key_value_array_ffi_entry_point_reads_every_pair_in_order() {
+        let keys: Vec<CString> = (0..3)
+            .map(|i| CString::new(format!("field{i}")).unwrap())
+            .collect();
+        let pairs: Vec<KeyParameterPair> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| KeyParameterPair {
+                key: key.as_ptr(),
+                key_length: key.as_bytes().len() as c_uint,
+                value: Parameter {
+                    kind: EParameterKind::Int64,
+                    value: ParameterValue {
+                        i64: i as c_longlong,
+                    },
+                    value_length: 0,
+                },
+            })
+            .collect();
+
+        let param = Parameter {
+            kind: EParameterKind::KeyValueArray,
+            value: ParameterValue {
+                key_parameter_array: pairs.as_ptr(),
+            },
+            value_length: pairs.len() as c_uint,
+        };
+
+        let command_parameter = unsafe { param.to_command_parameter() }.unwrap();
+        assert_eq!(
+            flatten(&command_parameter),
+            vec![
+                b"field0".to_vec(),
+                b"0".to_vec(),
+                b"field1".to_vec(),
+                b"1".to_vec(),
+                b"field2".to_vec(),
+                b"2".to_vec(),
+            ]
+        );
+    }
 }
\ No newline at end of file
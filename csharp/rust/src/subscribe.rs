@@ -0,0 +1,611 @@
+use crate::apihandle::Handle;
+use crate::compression::{self, CompressionConfig};
+use redis::{Cmd, PushInfo, PushKind, RedisError, Value};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_int, c_uchar};
+use std::panic::catch_unwind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Distinguishes the RESP3 push message kinds [`csharp_subscribe`] can surface.
+#[repr(C)]
+pub enum ESubscriptionKind {
+    Message,
+    PMessage,
+    SMessage,
+}
+
+/// Which subscribe command `csharp_subscribe` issues for a channel set. The server tracks these
+/// three namespaces independently (a plain channel, a glob pattern, and a shard channel), so a
+/// caller picks exactly one per call rather than mixing them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(C)]
+pub enum ESubscribeKind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+impl ESubscribeKind {
+    fn subscribe_command(self) -> &'static str {
+        match self {
+            ESubscribeKind::Channel => "SUBSCRIBE",
+            ESubscribeKind::Pattern => "PSUBSCRIBE",
+            ESubscribeKind::Shard => "SSUBSCRIBE",
+        }
+    }
+
+    fn unsubscribe_command(self) -> &'static str {
+        match self {
+            ESubscribeKind::Channel => "UNSUBSCRIBE",
+            ESubscribeKind::Pattern => "PUNSUBSCRIBE",
+            ESubscribeKind::Shard => "SUNSUBSCRIBE",
+        }
+    }
+}
+
+/// # Summary
+/// Callback re-invoked for every push message delivered to a subscription, until
+/// `csharp_unsubscribe` is called for the returned handle.
+///
+/// # Params
+/// ***in_data*** The data passed to `csharp_subscribe` as `in_callback_data`.
+/// ***in_kind*** Which of `SUBSCRIBE`/`PSUBSCRIBE`/`SSUBSCRIBE` produced this message.
+/// ***in_channel*** The channel the message was published on (not null-terminated).
+/// ***in_channel_len*** The length, in bytes, of `in_channel`.
+/// ***in_payload*** The published payload (not null-terminated, not necessarily valid UTF-8).
+/// ***in_payload_len*** The length, in bytes, of `in_payload`.
+///
+/// # Remarks
+/// The pointers passed to the callback are only valid for the duration of the call and must be
+/// copied by the callback if the data is needed afterward.
+pub type SubscriptionCallback = extern "C-unwind" fn(
+    in_data: *mut c_void,
+    in_kind: ESubscriptionKind,
+    in_channel: *const c_char,
+    in_channel_len: c_int,
+    in_payload: *const c_uchar,
+    in_payload_len: c_int,
+);
+
+struct Subscription {
+    callback: SubscriptionCallback,
+    // Safety: the C# side guarantees `data` outlives the subscription, matching the contract of
+    // `in_callback_data` on every other callback-based entry point in this crate.
+    data: usize,
+}
+unsafe impl Send for Subscription {}
+
+type SubscriptionMap = HashMap<String, HashMap<u64, Subscription>>;
+
+/// Subscriptions active on a single `Handle`. Kept in three independent maps — channel, pattern,
+/// shard channel — matching the server's own namespacing, so e.g. a plain channel and a shard
+/// channel of the same name don't collide. A single dispatcher task (spawned the first time a
+/// `Handle` is subscribed to) drains the handle's push-message receiver and fans each message out
+/// to every registration whose channel/pattern matches.
+#[derive(Default)]
+struct Registry {
+    by_channel: SubscriptionMap,
+    by_pattern: SubscriptionMap,
+    by_shard_channel: SubscriptionMap,
+    dispatcher_running: bool,
+}
+
+impl Registry {
+    fn map(&self, kind: ESubscribeKind) -> &SubscriptionMap {
+        match kind {
+            ESubscribeKind::Channel => &self.by_channel,
+            ESubscribeKind::Pattern => &self.by_pattern,
+            ESubscribeKind::Shard => &self.by_shard_channel,
+        }
+    }
+
+    fn map_mut(&mut self, kind: ESubscribeKind) -> &mut SubscriptionMap {
+        match kind {
+            ESubscribeKind::Channel => &mut self.by_channel,
+            ESubscribeKind::Pattern => &mut self.by_pattern,
+            ESubscribeKind::Shard => &mut self.by_shard_channel,
+        }
+    }
+}
+
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(1);
+static REGISTRIES: Mutex<Option<HashMap<usize, Arc<Mutex<Registry>>>>> = Mutex::new(None);
+
+fn registry_for(handle_key: usize) -> Arc<Mutex<Registry>> {
+    let mut registries = REGISTRIES.lock().unwrap();
+    registries
+        .get_or_insert_with(HashMap::new)
+        .entry(handle_key)
+        .or_insert_with(|| Arc::new(Mutex::new(Registry::default())))
+        .clone()
+}
+
+fn invoke_callback(
+    subscription: &Subscription,
+    kind: ESubscriptionKind,
+    channel: &[u8],
+    payload: &[u8],
+) {
+    let callback = subscription.callback;
+    let data = subscription.data as *mut c_void;
+    if let Err(e) = catch_unwind(|| {
+        callback(
+            data,
+            kind,
+            channel.as_ptr() as *const c_char,
+            channel.len() as c_int,
+            payload.as_ptr() as *const c_uchar,
+            payload.len() as c_int,
+        );
+    }) {
+        logger_core::log_error("csharp_ffi", format!("Exception in C# subscription callback: {:?}", e));
+    }
+}
+
+/// Reverses [`crate::apihandle::arg_with_compression`]'s framing on a single bulk value, the way
+/// [`compression::inflate_if_enabled`] does for a full reply `Value` tree.
+fn inflate_bytes(compression: &CompressionConfig, bytes: Vec<u8>) -> Vec<u8> {
+    match compression::inflate_if_enabled(compression, Value::BulkString(bytes)) {
+        Value::BulkString(inflated) => inflated,
+        other => unreachable!("inflating a BulkString can't change its Value variant: {other:?}"),
+    }
+}
+
+/// Converts a single RESP3 push message into (kind, registry lookup key, actual channel, payload)
+/// and dispatches it to every registration matching the lookup key (exact channel match for
+/// `Message`/`SMessage`, the subscribed pattern for `PMessage` — a caller registers under the
+/// pattern, so that's what the registry is keyed by). The lookup key and the delivered channel
+/// differ only for `PMessage`: the callback must still see the real channel the message was
+/// published on, not the pattern it matched.
+///
+/// `compression` mirrors [`crate::apihandle::Handle::command`]'s reply-side decompression: a
+/// publisher's payload goes through the same `arg_with_compression` path as any other command
+/// argument, so a payload over the compression threshold arrives here still framed and must be
+/// inflated before reaching the callback.
+fn dispatch(registry: &Mutex<Registry>, compression: &CompressionConfig, push: PushInfo) {
+    let (kind, subscribe_kind, lookup_key, channel, payload) = match push.kind {
+        PushKind::Message => match push.data.as_slice() {
+            [Value::BulkString(channel), Value::BulkString(payload)] => (
+                ESubscriptionKind::Message,
+                ESubscribeKind::Channel,
+                channel.clone(),
+                channel.clone(),
+                payload.clone(),
+            ),
+            _ => return,
+        },
+        PushKind::PMessage => match push.data.as_slice() {
+            [Value::BulkString(pattern), Value::BulkString(channel), Value::BulkString(payload)] => {
+                (
+                    ESubscriptionKind::PMessage,
+                    ESubscribeKind::Pattern,
+                    pattern.clone(),
+                    channel.clone(),
+                    payload.clone(),
+                )
+            }
+            _ => return,
+        },
+        PushKind::SMessage => match push.data.as_slice() {
+            [Value::BulkString(channel), Value::BulkString(payload)] => (
+                ESubscriptionKind::SMessage,
+                ESubscribeKind::Shard,
+                channel.clone(),
+                channel.clone(),
+                payload.clone(),
+            ),
+            _ => return,
+        },
+        _ => return,
+    };
+    let lookup_key = match std::str::from_utf8(&lookup_key) {
+        Ok(d) => d.to_string(),
+        Err(_) => return,
+    };
+    let channel = inflate_bytes(compression, channel);
+    let payload = inflate_bytes(compression, payload);
+    let registry = registry.lock().unwrap();
+    if let Some(subscriptions) = registry.map(subscribe_kind).get(&lookup_key) {
+        for subscription in subscriptions.values() {
+            invoke_callback(subscription, kind_clone(&kind), &channel, &payload);
+        }
+    }
+}
+
+fn kind_clone(kind: &ESubscriptionKind) -> ESubscriptionKind {
+    match kind {
+        ESubscriptionKind::Message => ESubscriptionKind::Message,
+        ESubscriptionKind::PMessage => ESubscriptionKind::PMessage,
+        ESubscriptionKind::SMessage => ESubscriptionKind::SMessage,
+    }
+}
+
+/// Ensures a dispatcher task is draining `handle`'s push-message receiver into `registry`.
+/// Only the first `csharp_subscribe` call for a given `Handle` actually spawns the task; later
+/// calls just add to the existing registry.
+fn ensure_dispatcher(handle: &Handle, registry: Arc<Mutex<Registry>>) {
+    let mut guard = registry.lock().unwrap();
+    if guard.dispatcher_running {
+        return;
+    }
+    let Some(mut receiver) = handle.take_push_receiver() else {
+        // Another subscribe call already took the receiver and is running the dispatcher.
+        return;
+    };
+    guard.dispatcher_running = true;
+    drop(guard);
+
+    let registry = registry.clone();
+    let compression = handle.compression();
+    tokio::spawn(async move {
+        logger_core::log_trace("csharp_ffi", "Entered subscription dispatcher task");
+        while let Some(push) = receiver.recv().await {
+            dispatch(&registry, &compression, push);
+        }
+        logger_core::log_trace("csharp_ffi", "Exiting subscription dispatcher task");
+    });
+}
+
+/// The subset of `channels` that have no local subscriber yet under `kind` — the ones that
+/// actually need a `SUBSCRIBE`/`PSUBSCRIBE`/`SSUBSCRIBE` sent, since the shared connection already
+/// fans an already-subscribed channel's messages out to every local subscriber.
+fn channels_without_local_subscriber(
+    registry: &Registry,
+    kind: ESubscribeKind,
+    channels: &[String],
+) -> Vec<String> {
+    channels
+        .iter()
+        .filter(|channel| !registry.map(kind).contains_key(channel.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Records a new local subscription under `kind` for every one of `channels`.
+fn register_subscription(
+    registry: &mut Registry,
+    kind: ESubscribeKind,
+    channels: Vec<String>,
+    id: u64,
+    callback: SubscriptionCallback,
+    callback_data: *mut c_void,
+) {
+    let map = registry.map_mut(kind);
+    for channel in channels {
+        map.entry(channel).or_default().insert(
+            id,
+            Subscription {
+                callback,
+                data: callback_data as usize,
+            },
+        );
+    }
+}
+
+/// Removes `subscription_id` from every channel/pattern/shard-channel registration, returning the
+/// ones left with no local subscriber as a result — the ones that need an
+/// `UNSUBSCRIBE`/`PUNSUBSCRIBE`/`SUNSUBSCRIBE` sent.
+fn remove_subscription(registry: &mut Registry, subscription_id: u64) -> Vec<(ESubscribeKind, String)> {
+    let mut emptied = Vec::new();
+    for kind in [
+        ESubscribeKind::Channel,
+        ESubscribeKind::Pattern,
+        ESubscribeKind::Shard,
+    ] {
+        registry.map_mut(kind).retain(|channel, subscriptions| {
+            subscriptions.remove(&subscription_id);
+            if subscriptions.is_empty() {
+                emptied.push((kind, channel.clone()));
+                false
+            } else {
+                true
+            }
+        });
+    }
+    emptied
+}
+
+/// Re-adds `channel` to `registry` with no local subscribers, for when its server-side
+/// `UNSUBSCRIBE` failed: the channel is no longer locally subscribed, but the server still
+/// considers it subscribed, so the entry must survive for a later subscribe/unsubscribe call (or
+/// `forget`) to pick up the cleanup.
+fn restore_emptied_channel(registry: &mut Registry, kind: ESubscribeKind, channel: String) {
+    registry.map_mut(kind).entry(channel).or_default();
+}
+
+/// Registers `callback` to be invoked for every message published to `channels`, spawning the
+/// shared dispatcher for `handle` if this is its first subscription, and issuing
+/// `SUBSCRIBE`/`PSUBSCRIBE`/`SSUBSCRIBE` (per `kind`) to the server for whichever of `channels`
+/// has no local subscriber yet. Returns an opaque handle to pass to `csharp_unsubscribe`.
+pub(crate) async fn subscribe(
+    handle_key: usize,
+    handle: &Handle,
+    kind: ESubscribeKind,
+    channels: Vec<String>,
+    callback: SubscriptionCallback,
+    callback_data: *mut c_void,
+) -> Result<u64, RedisError> {
+    let registry = registry_for(handle_key);
+    ensure_dispatcher(handle, registry.clone());
+
+    let new_channels = channels_without_local_subscriber(&registry.lock().unwrap(), kind, &channels);
+    if !new_channels.is_empty() {
+        let mut cmd = Cmd::new();
+        cmd.arg(kind.subscribe_command());
+        for channel in &new_channels {
+            cmd.arg(channel);
+        }
+        handle.command(cmd, &[], None).await?;
+    }
+
+    let id = NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed);
+    register_subscription(
+        &mut registry.lock().unwrap(),
+        kind,
+        channels,
+        id,
+        callback,
+        callback_data,
+    );
+    Ok(id)
+}
+
+/// Removes every channel registration made under `subscription_id` for `handle_key`, issuing
+/// `UNSUBSCRIBE`/`PUNSUBSCRIBE`/`SUNSUBSCRIBE` to the server for any channel/pattern that no
+/// longer has a local subscriber as a result.
+pub(crate) async fn unsubscribe(
+    handle_key: usize,
+    handle: &Handle,
+    subscription_id: u64,
+) -> Result<(), RedisError> {
+    let registry = registry_for(handle_key);
+    let emptied = remove_subscription(&mut registry.lock().unwrap(), subscription_id);
+    let mut first_err = None;
+    for (kind, channel) in emptied {
+        let mut cmd = Cmd::new();
+        cmd.arg(kind.unsubscribe_command()).arg(&channel);
+        if let Err(err) = handle.command(cmd, &[], None).await {
+            // Put the channel back so a failed UNSUBSCRIBE doesn't silently drop local
+            // bookkeeping for a channel the server still considers us subscribed to; a later
+            // subscribe/unsubscribe call (or `forget`) can pick up the cleanup.
+            restore_emptied_channel(&mut registry.lock().unwrap(), kind, channel);
+            first_err.get_or_insert(err);
+        }
+    }
+    match first_err {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Drops `handle_key`'s entire registry, if any. Must be called when the corresponding `Handle`
+/// is freed: the dispatcher task it may have spawned exits on its own once the push-message
+/// receiver closes, but the registry entry itself won't be reclaimed otherwise.
+pub(crate) fn forget(handle_key: usize) {
+    if let Some(registries) = REGISTRIES.lock().unwrap().as_mut() {
+        registries.remove(&handle_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::CompressionMode;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static RECEIVED: RefCell<Vec<(&'static str, String, Vec<u8>)>> = RefCell::new(Vec::new());
+    }
+
+    extern "C-unwind" fn record_callback(
+        _data: *mut c_void,
+        kind: ESubscriptionKind,
+        channel: *const c_char,
+        channel_len: c_int,
+        payload: *const c_uchar,
+        payload_len: c_int,
+    ) {
+        let channel =
+            unsafe { std::slice::from_raw_parts(channel as *const u8, channel_len as usize) }
+                .to_vec();
+        let payload =
+            unsafe { std::slice::from_raw_parts(payload, payload_len as usize) }.to_vec();
+        let kind = match kind {
+            ESubscriptionKind::Message => "message",
+            ESubscriptionKind::PMessage => "pmessage",
+            ESubscriptionKind::SMessage => "smessage",
+        };
+        RECEIVED.with(|cell| {
+            cell.borrow_mut()
+                .push((kind, String::from_utf8(channel).unwrap(), payload));
+        });
+    }
+
+    // Each test runs on its own thread and `dispatch` invokes callbacks synchronously on it, so a
+    // thread-local is enough to isolate each test's recorded callback invocations without a real
+    // C# caller's `data` pointer.
+    fn drain_received() -> Vec<(&'static str, String, Vec<u8>)> {
+        RECEIVED.with(|cell| std::mem::take(&mut *cell.borrow_mut()))
+    }
+
+    fn recording_subscription() -> Subscription {
+        Subscription {
+            callback: record_callback,
+            data: 0,
+        }
+    }
+
+    #[test]
+    fn dispatch_routes_message_to_the_matching_channel_subscriber() {
+        drain_received();
+        let mut registry = Registry::default();
+        registry
+            .by_channel
+            .entry("news".to_string())
+            .or_default()
+            .insert(1, recording_subscription());
+        let registry = Mutex::new(registry);
+        let push = PushInfo {
+            kind: PushKind::Message,
+            data: vec![
+                Value::BulkString(b"news".to_vec()),
+                Value::BulkString(b"hello".to_vec()),
+            ],
+        };
+
+        dispatch(&registry, &CompressionConfig::default(), push);
+
+        assert_eq!(
+            drain_received(),
+            vec![("message", "news".to_string(), b"hello".to_vec())]
+        );
+    }
+
+    #[test]
+    fn dispatch_delivers_the_real_channel_not_the_pattern_for_pmessage() {
+        drain_received();
+        let mut registry = Registry::default();
+        registry
+            .by_pattern
+            .entry("news.*".to_string())
+            .or_default()
+            .insert(1, recording_subscription());
+        let registry = Mutex::new(registry);
+        let push = PushInfo {
+            kind: PushKind::PMessage,
+            data: vec![
+                Value::BulkString(b"news.*".to_vec()),
+                Value::BulkString(b"news.sports".to_vec()),
+                Value::BulkString(b"score update".to_vec()),
+            ],
+        };
+
+        dispatch(&registry, &CompressionConfig::default(), push);
+
+        assert_eq!(
+            drain_received(),
+            vec![("pmessage", "news.sports".to_string(), b"score update".to_vec())]
+        );
+    }
+
+    #[test]
+    fn dispatch_ignores_a_shard_message_with_no_shard_subscriber() {
+        drain_received();
+        let registry = Mutex::new(Registry::default());
+        let push = PushInfo {
+            kind: PushKind::SMessage,
+            data: vec![
+                Value::BulkString(b"shard-chan".to_vec()),
+                Value::BulkString(b"hi".to_vec()),
+            ],
+        };
+
+        dispatch(&registry, &CompressionConfig::default(), push);
+
+        assert!(drain_received().is_empty());
+    }
+
+    #[test]
+    fn dispatch_ignores_push_kinds_it_does_not_understand() {
+        drain_received();
+        let registry = Mutex::new(Registry::default());
+        let push = PushInfo {
+            kind: PushKind::Disconnection,
+            data: vec![],
+        };
+
+        dispatch(&registry, &CompressionConfig::default(), push);
+
+        assert!(drain_received().is_empty());
+    }
+
+    #[test]
+    fn dispatch_inflates_a_compressed_payload_before_delivery() {
+        drain_received();
+        let compression = CompressionConfig {
+            mode: CompressionMode::Lz4,
+            threshold: 4,
+        };
+        let original = b"payload long enough to exceed the threshold".to_vec();
+        let framed = compression.compress(&original).expect("exceeds threshold");
+        let mut registry = Registry::default();
+        registry
+            .by_channel
+            .entry("ch".to_string())
+            .or_default()
+            .insert(1, recording_subscription());
+        let registry = Mutex::new(registry);
+        let push = PushInfo {
+            kind: PushKind::Message,
+            data: vec![Value::BulkString(b"ch".to_vec()), Value::BulkString(framed)],
+        };
+
+        dispatch(&registry, &compression, push);
+
+        assert_eq!(
+            drain_received(),
+            vec![("message", "ch".to_string(), original)]
+        );
+    }
+
+    #[test]
+    fn channels_without_local_subscriber_skips_already_subscribed_channels() {
+        let mut registry = Registry::default();
+        registry.by_channel.entry("a".to_string()).or_default();
+
+        let result = channels_without_local_subscriber(
+            &registry,
+            ESubscribeKind::Channel,
+            &["a".to_string(), "b".to_string()],
+        );
+
+        assert_eq!(result, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn register_subscription_adds_an_entry_per_channel() {
+        let mut registry = Registry::default();
+
+        register_subscription(
+            &mut registry,
+            ESubscribeKind::Pattern,
+            vec!["a.*".to_string(), "b.*".to_string()],
+            7,
+            record_callback,
+            std::ptr::null_mut(),
+        );
+
+        assert!(registry.by_pattern["a.*"].contains_key(&7));
+        assert!(registry.by_pattern["b.*"].contains_key(&7));
+    }
+
+    #[test]
+    fn remove_subscription_reports_only_channels_left_with_no_local_subscriber() {
+        let mut registry = Registry::default();
+        registry
+            .by_channel
+            .entry("a".to_string())
+            .or_default()
+            .insert(1, recording_subscription());
+        let shared = registry.by_channel.entry("b".to_string()).or_default();
+        shared.insert(1, recording_subscription());
+        shared.insert(2, recording_subscription());
+
+        let emptied = remove_subscription(&mut registry, 1);
+
+        assert_eq!(emptied, vec![(ESubscribeKind::Channel, "a".to_string())]);
+        assert!(!registry.by_channel.contains_key("a"));
+        assert!(registry.by_channel["b"].contains_key(&2));
+    }
+
+    #[test]
+    fn restore_emptied_channel_re_adds_an_entry_with_no_local_subscribers() {
+        let mut registry = Registry::default();
+
+        restore_emptied_channel(&mut registry, ESubscribeKind::Channel, "a".to_string());
+
+        assert!(registry.by_channel["a"].is_empty());
+    }
+}